@@ -0,0 +1,88 @@
+use std::io::Write;
+
+static AUDIT: std::sync::OnceLock<std::sync::Mutex<std::fs::File>> = std::sync::OnceLock::new();
+
+pub(crate) fn init(path: &str) -> std::io::Result<()> {
+    let f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    AUDIT
+        .set(std::sync::Mutex::new(f))
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::AlreadyExists))
+}
+
+pub(crate) fn is_enabled() -> bool {
+    AUDIT.get().is_some()
+}
+
+fn get_comm(pid: u32) -> String {
+    if libfs::os::is_linux()
+        && let Ok(v) = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+    {
+        v.trim_end().to_string()
+    } else {
+        "-".to_string()
+    }
+}
+
+// written on drop with errno of the reply, regardless of log level
+#[derive(Debug)]
+pub(crate) struct Record {
+    uid: u32,
+    gid: u32,
+    pid: u32,
+    op: &'static str,
+    inum: u64,
+    path: String,
+    arg: String,
+}
+
+impl Record {
+    pub(crate) fn new<F: FnOnce() -> String>(
+        req: &fuser::Request<'_>,
+        op: &'static str,
+        inum: u64,
+        arg: String,
+        path: F,
+    ) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+        Some(Self {
+            uid: req.uid(),
+            gid: req.gid(),
+            pid: req.pid(),
+            op,
+            inum,
+            path: path(),
+            arg,
+        })
+    }
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        let Some(f) = AUDIT.get() else {
+            return;
+        };
+        let line = format!(
+            "{} op={} uid={} gid={} pid={} comm={:?} inum={} path={:?} {} result={}\n",
+            crate::util::get_time_string(),
+            self.op,
+            self.uid,
+            self.gid,
+            self.pid,
+            get_comm(self.pid),
+            self.inum,
+            self.path,
+            self.arg,
+            crate::reqlog::get_errno(),
+        );
+        if let Ok(mut f) = f.lock()
+            && let Err(e) = f.write_all(line.as_bytes())
+        {
+            log::error!("{e}");
+        }
+    }
+}
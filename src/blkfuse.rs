@@ -0,0 +1,237 @@
+// single file FUSE mount exposing a container as a raw image,
+// since libhammer2 opens volumes by path
+const IMAGE_INUM: u64 = 2;
+const IMAGE_NAME: &str = "image";
+
+struct ImageFuse {
+    src: Box<dyn crate::blksrc::BlockSource>,
+    uid: u32,
+    gid: u32,
+}
+
+impl ImageFuse {
+    fn get_attr(&self, inum: u64) -> Option<fuser::FileAttr> {
+        let (kind, perm, size, nlink) = match inum {
+            fuser::FUSE_ROOT_ID => (fuser::FileType::Directory, 0o500, 0, 2),
+            IMAGE_INUM => (fuser::FileType::RegularFile, 0o400, self.src.get_size(), 1),
+            _ => return None,
+        };
+        let t = std::time::SystemTime::now();
+        Some(fuser::FileAttr {
+            ino: inum,
+            size,
+            blocks: size.div_ceil(512),
+            atime: t,
+            mtime: t,
+            ctime: t,
+            crtime: t,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 65536,
+            flags: 0,
+        })
+    }
+}
+
+impl fuser::Filesystem for ImageFuse {
+    fn lookup(
+        &mut self,
+        req: &fuser::Request<'_>,
+        dinum: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEntry,
+    ) {
+        if req.uid() != self.uid {
+            reply.error(libc::EACCES);
+            return;
+        }
+        if dinum != fuser::FUSE_ROOT_ID || name != IMAGE_NAME {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        match self.get_attr(IMAGE_INUM) {
+            Some(v) => reply.entry(&std::time::Duration::MAX, &v, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        inum: u64,
+        _fh: Option<u64>,
+        reply: fuser::ReplyAttr,
+    ) {
+        match self.get_attr(inum) {
+            Some(v) => reply.attr(&std::time::Duration::MAX, &v),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    // no page cache, libhammer2 caches what it needs
+    fn open(&mut self, req: &fuser::Request<'_>, inum: u64, _flags: i32, reply: fuser::ReplyOpen) {
+        if req.uid() != self.uid {
+            reply.error(libc::EACCES);
+        } else if inum == IMAGE_INUM {
+            reply.opened(0, fuser::consts::FOPEN_DIRECT_IO);
+        } else {
+            reply.error(libc::EISDIR);
+        }
+    }
+
+    fn read(
+        &mut self,
+        req: &fuser::Request<'_>,
+        inum: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyData,
+    ) {
+        if req.uid() != self.uid {
+            reply.error(libc::EACCES);
+            return;
+        }
+        let (Ok(offset), Ok(size)) = (u64::try_from(offset), usize::try_from(size)) else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+        if inum != IMAGE_INUM {
+            reply.error(libc::EISDIR);
+            return;
+        }
+        let mut buf = vec![0; size];
+        match self.src.read_at(&mut buf, offset) {
+            Ok(n) => reply.data(&buf[..n]),
+            Err(e) => {
+                log::error!("offset {offset} size {size}: {e}");
+                reply.error(libc::EIO);
+            }
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        inum: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: fuser::ReplyDirectory,
+    ) {
+        if inum != fuser::FUSE_ROOT_ID {
+            reply.error(libc::ENOTDIR);
+            return;
+        }
+        let v = [
+            (fuser::FUSE_ROOT_ID, fuser::FileType::Directory, "."),
+            (fuser::FUSE_ROOT_ID, fuser::FileType::Directory, ".."),
+            (IMAGE_INUM, fuser::FileType::RegularFile, IMAGE_NAME),
+        ];
+        for (i, x) in v.iter().enumerate().skip(offset.try_into().unwrap_or(0)) {
+            if reply.add(x.0, (i + 1).try_into().unwrap(), x.1, x.2) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+// unmounted on drop
+pub(crate) struct Helper {
+    session: Option<fuser::BackgroundSession>,
+    dir: String,
+}
+
+impl Helper {
+    pub(crate) fn get_path(&self) -> String {
+        format!("{}/{IMAGE_NAME}", self.dir)
+    }
+
+    pub(crate) fn get_spec(&self, label: Option<&str>) -> String {
+        match label {
+            Some(v) => format!("{}@{v}", self.get_path()),
+            None => self.get_path(),
+        }
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        drop(self.session.take());
+        if let Err(e) = std::fs::remove_dir(&self.dir) {
+            log::error!("{}: {e}", self.dir);
+        }
+    }
+}
+
+// auto_unmount implies allow_other, which non-root users
+// can only set if permitted in fuse.conf
+fn can_auto_unmount() -> bool {
+    if !libfs::os::is_linux() {
+        return false;
+    }
+    if nix::unistd::getuid().is_root() {
+        return true;
+    }
+    std::fs::read_to_string("/etc/fuse.conf").is_ok_and(|s| {
+        s.lines()
+            .any(|l| l.split('#').next().unwrap_or_default().trim() == "user_allow_other")
+    })
+}
+
+// threads don't survive fork, so a helper mounted before daemonize
+// must be dropped and mounted again after
+pub(crate) fn mount(src: Box<dyn crate::blksrc::BlockSource>) -> std::io::Result<Helper> {
+    let dir = std::env::temp_dir()
+        .join(format!("hammer2-fuse.{}", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+    std::os::unix::fs::DirBuilderExt::mode(&mut std::fs::DirBuilder::new(), 0o700).create(&dir)?;
+    let fs = ImageFuse {
+        src,
+        uid: nix::unistd::getuid().as_raw(),
+        gid: nix::unistd::getgid().as_raw(),
+    };
+    // the mount hides the directory mode, and auto_unmount implies
+    // allow_other, so access is limited to the mounting user
+    let mut fopt = vec![
+        fuser::MountOption::RO,
+        fuser::MountOption::FSName("hammer2-fuse-image".to_string()),
+        fuser::MountOption::DefaultPermissions,
+    ];
+    // unmounted by fusermount if killed
+    if can_auto_unmount() {
+        fopt.push(fuser::MountOption::AutoUnmount);
+    }
+    match fuser::spawn_mount2(fs, &dir, &fopt) {
+        Ok(v) => Ok(Helper {
+            session: Some(v),
+            dir,
+        }),
+        Err(e) => {
+            let _ = std::fs::remove_dir(&dir);
+            Err(e)
+        }
+    }
+}
+
+// helper mount if special is a container, and path to pass to libhammer2
+pub(crate) fn mount_special(
+    special: &str,
+    selector: Option<&str>,
+) -> std::io::Result<(Option<Helper>, String)> {
+    match crate::blksrc::open(special, selector)? {
+        Some(src) => {
+            let h = mount(src)?;
+            let path = h.get_path();
+            Ok((Some(h), path))
+        }
+        None => Ok((None, special.to_string())),
+    }
+}
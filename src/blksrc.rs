@@ -0,0 +1,519 @@
+use std::os::unix::fs::FileExt;
+
+// random access to a HAMMER2 image stored in a container file
+pub(crate) trait BlockSource: Send {
+    fn get_size(&self) -> u64;
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize>;
+}
+
+const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+const ZSTD_SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const ZSTD_SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+const ZSTD_SEEKABLE_FOOTER_SIZE: u64 = 9;
+const ZSTD_SEEKABLE_CHECKSUM: u8 = 1 << 7;
+
+fn invalid_data(s: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, s)
+}
+
+fn read_u32(file: &std::fs::File, offset: u64) -> std::io::Result<u32> {
+    let mut b = [0; 4];
+    file.read_exact_at(&mut b, offset)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+// block device or raw image
+#[derive(Debug)]
+struct RawFile {
+    file: std::fs::File,
+    size: u64,
+}
+
+impl RawFile {
+    fn new(f: &str) -> std::io::Result<Self> {
+        let mut file = std::fs::File::open(f)?;
+        let size = std::io::Seek::seek(&mut file, std::io::SeekFrom::End(0))?;
+        Ok(Self { file, size })
+    }
+}
+
+impl BlockSource for RawFile {
+    fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let n = std::cmp::min(buf.len() as u64, self.size - offset) as usize;
+        self.file.read_at(&mut buf[..n], offset)
+    }
+}
+
+// partition within a disk
+struct Slice {
+    src: Box<dyn BlockSource>,
+    offset: u64,
+    size: u64,
+}
+
+impl BlockSource for Slice {
+    fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let n = std::cmp::min(buf.len() as u64, self.size - offset) as usize;
+        self.src.read_at(&mut buf[..n], self.offset + offset)
+    }
+}
+
+// "image.000", "image.001", ...
+#[derive(Debug)]
+struct SplitFile {
+    files: Vec<(std::fs::File, u64)>, // file, start offset
+    size: u64,
+}
+
+impl SplitFile {
+    fn new(f: &str) -> std::io::Result<Option<Self>> {
+        let Some((base, ext)) = f.rsplit_once('.') else {
+            return Ok(None);
+        };
+        if ext.is_empty() || !ext.bytes().all(|x| x.is_ascii_digit()) || ext.parse() != Ok(0) {
+            return Ok(None);
+        }
+        let mut files = vec![];
+        let mut size = 0;
+        loop {
+            let f = format!("{base}.{:0width$}", files.len(), width = ext.len());
+            let file = match std::fs::File::open(&f) {
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && !files.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            let n = file.metadata()?.len();
+            log::info!("{f}: {n} bytes at {size}");
+            files.push((file, size));
+            size += n;
+        }
+        if files.len() < 2 {
+            return Ok(None);
+        }
+        Ok(Some(Self { files, size }))
+    }
+}
+
+impl BlockSource for SplitFile {
+    fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            let off = offset + n as u64;
+            if off >= self.size {
+                break;
+            }
+            let i = self.files.partition_point(|x| x.1 <= off) - 1;
+            let (file, start) = &self.files[i];
+            let x = file.read_at(&mut buf[n..], off - start)?;
+            if x == 0 {
+                break; // truncated chunk
+            }
+            n += x;
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Debug)]
+struct ZstdFrame {
+    coff: u64,
+    csize: u64,
+    doff: u64,
+    dsize: u64,
+}
+
+// zstd seekable format with seek table in a trailing skippable frame
+#[derive(Debug)]
+struct ZstdSeekable {
+    file: std::fs::File,
+    frames: Vec<ZstdFrame>,
+    size: u64,
+    cache: Option<(usize, Vec<u8>)>, // last decompressed frame
+}
+
+impl ZstdSeekable {
+    fn new(file: std::fs::File) -> std::io::Result<Self> {
+        let flen = file.metadata()?.len();
+        if flen < ZSTD_SEEKABLE_FOOTER_SIZE + 8 {
+            return Err(invalid_data("zstd file too small"));
+        }
+        let mut b = [0; ZSTD_SEEKABLE_FOOTER_SIZE as usize];
+        file.read_exact_at(&mut b, flen - ZSTD_SEEKABLE_FOOTER_SIZE)?;
+        let nframes = u32::from_le_bytes(b[..4].try_into().unwrap());
+        let desc = b[4];
+        if u32::from_le_bytes(b[5..].try_into().unwrap()) != ZSTD_SEEKABLE_MAGIC {
+            return Err(invalid_data("zstd file not seekable format"));
+        }
+        let esize = if desc & ZSTD_SEEKABLE_CHECKSUM != 0 {
+            12
+        } else {
+            8
+        };
+        let tsize = u64::from(nframes) * esize + ZSTD_SEEKABLE_FOOTER_SIZE;
+        if tsize + 8 > flen {
+            return Err(invalid_data("invalid zstd seek table size"));
+        }
+        let toff = flen - tsize;
+        if read_u32(&file, toff - 8)? != ZSTD_SKIPPABLE_MAGIC
+            || u64::from(read_u32(&file, toff - 4)?) != tsize
+        {
+            return Err(invalid_data("invalid zstd seek table header"));
+        }
+        let mut t = vec![0; (tsize - ZSTD_SEEKABLE_FOOTER_SIZE).try_into().unwrap()];
+        file.read_exact_at(&mut t, toff)?;
+        let mut frames = vec![];
+        let (mut coff, mut doff) = (0, 0);
+        for e in t.chunks_exact(esize.try_into().unwrap()) {
+            let csize = u64::from(u32::from_le_bytes(e[..4].try_into().unwrap()));
+            let dsize = u64::from(u32::from_le_bytes(e[4..8].try_into().unwrap()));
+            frames.push(ZstdFrame {
+                coff,
+                csize,
+                doff,
+                dsize,
+            });
+            coff += csize;
+            doff += dsize;
+        }
+        if coff != toff - 8 {
+            return Err(invalid_data("zstd seek table inconsistent with file size"));
+        }
+        log::info!("zstd seekable: {nframes} frames, {doff} bytes");
+        Ok(Self {
+            file,
+            frames,
+            size: doff,
+            cache: None,
+        })
+    }
+
+    fn get_frame(&mut self, i: usize) -> std::io::Result<&[u8]> {
+        if self.cache.as_ref().is_none_or(|x| x.0 != i) {
+            let f = &self.frames[i];
+            let mut b = vec![0; f.csize.try_into().map_err(|_| invalid_data("frame size"))?];
+            self.file.read_exact_at(&mut b, f.coff)?;
+            let v = zstd::bulk::decompress(
+                &b,
+                f.dsize.try_into().map_err(|_| invalid_data("frame size"))?,
+            )?;
+            if v.len() as u64 != f.dsize {
+                return Err(invalid_data("zstd frame size mismatch"));
+            }
+            self.cache = Some((i, v));
+        }
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+}
+
+impl BlockSource for ZstdSeekable {
+    fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            let off = offset + n as u64;
+            if off >= self.size {
+                break;
+            }
+            let i = self.frames.partition_point(|x| x.doff <= off) - 1;
+            let beg = usize::try_from(off - self.frames[i].doff).unwrap();
+            let b = self.get_frame(i)?;
+            let x = std::cmp::min(buf.len() - n, b.len() - beg);
+            buf[n..n + x].copy_from_slice(&b[beg..beg + x]);
+            n += x;
+        }
+        Ok(n)
+    }
+}
+
+// None if special is not a container, e.g. block device or raw image
+fn open_container(special: &str) -> std::io::Result<Option<Box<dyn BlockSource>>> {
+    let Ok(t) = std::fs::metadata(special) else {
+        return Ok(None); // let libhammer2 report
+    };
+    if !t.is_file() {
+        return Ok(None);
+    }
+    let file = std::fs::File::open(special)?;
+    if t.len() >= 4 && read_u32(&file, 0)? == ZSTD_MAGIC {
+        return Ok(Some(Box::new(ZstdSeekable::new(file)?)));
+    }
+    if let Some(v) = SplitFile::new(special)? {
+        return Ok(Some(Box::new(v)));
+    }
+    Ok(None)
+}
+
+// reads zeros in masked ranges
+struct Masked {
+    src: Box<dyn BlockSource>,
+    ranges: Vec<(u64, u64)>,
+}
+
+impl BlockSource for Masked {
+    fn get_size(&self) -> u64 {
+        self.src.get_size()
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        let n = self.src.read_at(buf, offset)?;
+        let end = offset + n as u64;
+        for &(beg, x) in &self.ranges {
+            let beg = std::cmp::max(beg, offset);
+            let x = std::cmp::min(x, end);
+            if beg < x {
+                buf[(beg - offset) as usize..(x - offset) as usize].fill(0);
+            }
+        }
+        Ok(n)
+    }
+}
+
+pub(crate) fn mask(src: Box<dyn BlockSource>, ranges: Vec<(u64, u64)>) -> Box<dyn BlockSource> {
+    Box::new(Masked { src, ranges })
+}
+
+// None if special can be passed to libhammer2 as is
+pub(crate) fn open(
+    special: &str,
+    selector: Option<&str>,
+) -> std::io::Result<Option<Box<dyn BlockSource>>> {
+    let src = open_container(special)?;
+    let Some(sel) = selector else {
+        return Ok(src);
+    };
+    let mut src = match src {
+        Some(v) => v,
+        None => Box::new(RawFile::new(special)?),
+    };
+    let (offset, size) = crate::part::find(&mut *src, sel)?;
+    log::info!("{special}#{sel}: offset {offset} size {size}");
+    Ok(Some(Box::new(Slice { src, offset, size })))
+}
+
+// raw access to special even if not a container
+pub(crate) fn open_raw(
+    special: &str,
+    selector: Option<&str>,
+) -> std::io::Result<Box<dyn BlockSource>> {
+    match open(special, selector)? {
+        Some(v) => Ok(v),
+        None => Ok(Box::new(RawFile::new(special)?)),
+    }
+}
+
+// "special[#selector][@label]" or "special@offset=N[,size=M][@label]"
+pub(crate) fn parse_spec(spec: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (special, label) = match spec.split_once('@') {
+        Some((a, b)) => (a, Some(b)),
+        None => (spec, None),
+    };
+    if let Some(v) = label
+        && v.starts_with("offset=")
+    {
+        return match v.split_once('@') {
+            Some((a, b)) => (special, Some(a), Some(b)),
+            None => (special, Some(v), None),
+        };
+    }
+    if std::path::Path::new(special).exists() {
+        return (special, None, label);
+    }
+    match special.rsplit_once('#') {
+        Some((a, b)) => (a, Some(b), label),
+        None => (special, None, label),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    struct TempFile(String);
+
+    impl TempFile {
+        fn new(name: &str, b: &[u8]) -> Self {
+            let f = std::env::temp_dir()
+                .join(format!("hammer2-fuse-{name}.{}", std::process::id()))
+                .to_string_lossy()
+                .into_owned();
+            std::fs::write(&f, b).unwrap();
+            Self(f)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn get_data(n: usize) -> Vec<u8> {
+        (0..n).map(|x| u8::try_from(x % 251).unwrap()).collect()
+    }
+
+    // frames of chunk bytes followed by seek table
+    fn get_seekable(data: &[u8], chunk: usize, checksum: bool) -> Vec<u8> {
+        let mut b = vec![];
+        let mut t = vec![];
+        let mut nframes = 0_u32;
+        for x in data.chunks(chunk) {
+            let c = zstd::bulk::compress(x, 1).unwrap();
+            t.extend_from_slice(&u32::try_from(c.len()).unwrap().to_le_bytes());
+            t.extend_from_slice(&u32::try_from(x.len()).unwrap().to_le_bytes());
+            if checksum {
+                t.extend_from_slice(&[0; 4]);
+            }
+            b.extend_from_slice(&c);
+            nframes += 1;
+        }
+        t.extend_from_slice(&nframes.to_le_bytes());
+        t.push(if checksum {
+            super::ZSTD_SEEKABLE_CHECKSUM
+        } else {
+            0
+        });
+        t.extend_from_slice(&super::ZSTD_SEEKABLE_MAGIC.to_le_bytes());
+        b.extend_from_slice(&super::ZSTD_SKIPPABLE_MAGIC.to_le_bytes());
+        b.extend_from_slice(&u32::try_from(t.len()).unwrap().to_le_bytes());
+        b.extend_from_slice(&t);
+        b
+    }
+
+    fn read_all(src: &mut dyn super::BlockSource, offset: u64, n: usize) -> Vec<u8> {
+        let mut b = vec![0; n];
+        let n = src.read_at(&mut b, offset).unwrap();
+        b.truncate(n);
+        b
+    }
+
+    #[test]
+    fn test_zstd_seekable() {
+        let data = get_data(10000);
+        for checksum in [false, true] {
+            let f = TempFile::new("zstd", &get_seekable(&data, 4096, checksum));
+            let mut src = super::open_container(&f.0).unwrap().unwrap();
+            assert_eq!(src.get_size(), 10000);
+            assert_eq!(read_all(&mut *src, 0, 10000), data);
+            // across frames
+            assert_eq!(read_all(&mut *src, 4000, 5000), data[4000..9000]);
+            assert_eq!(read_all(&mut *src, 9990, 100), data[9990..]);
+            assert!(read_all(&mut *src, 10000, 100).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_zstd_invalid() {
+        let data = get_data(10000);
+        let b = get_seekable(&data, 4096, false);
+        // seek table magic
+        let mut x = b.clone();
+        let n = x.len();
+        x[n - 1] ^= 1;
+        let f = TempFile::new("zstd-magic", &x);
+        assert!(super::open_container(&f.0).is_err());
+        // frame count
+        let mut x = b.clone();
+        x[n - 9] += 1;
+        let f = TempFile::new("zstd-nframes", &x);
+        assert!(super::open_container(&f.0).is_err());
+        // not seekable
+        let x = zstd::bulk::compress(&data, 1).unwrap();
+        let f = TempFile::new("zstd-plain", &x);
+        assert!(super::open_container(&f.0).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        let data = get_data(10000);
+        let dir = std::env::temp_dir().join(format!("hammer2-fuse-split.{}", std::process::id()));
+        let _ = std::fs::create_dir(&dir);
+        let base = dir.join("image").to_string_lossy().into_owned();
+        let l: Vec<_> = data
+            .chunks(3000)
+            .enumerate()
+            .map(|(i, x)| {
+                let f = format!("{base}.{i:03}");
+                std::fs::write(&f, x).unwrap();
+                f
+            })
+            .collect();
+        let mut src = super::open_container(&l[0]).unwrap().unwrap();
+        let v = (
+            src.get_size(),
+            read_all(&mut *src, 0, 10000),
+            read_all(&mut *src, 2990, 20),
+        );
+        assert!(super::open_container(&l[1]).unwrap().is_none());
+        for f in &l {
+            std::fs::remove_file(f).unwrap();
+        }
+        std::fs::remove_dir(&dir).unwrap();
+        assert_eq!(v.0, 10000);
+        assert_eq!(v.1, data);
+        assert_eq!(v.2, data[2990..3010]);
+    }
+
+    #[test]
+    fn test_mask() {
+        let data = get_data(1000);
+        let f = TempFile::new("mask", &data);
+        let src = Box::new(super::RawFile::new(&f.0).unwrap());
+        let mut src = super::mask(src, vec![(10, 20), (990, 2000)]);
+        let v = read_all(&mut *src, 5, 1000);
+        assert_eq!(v.len(), 995);
+        assert_eq!(v[..5], data[5..10]);
+        assert!(v[5..15].iter().all(|&x| x == 0));
+        assert_eq!(v[15..985], data[20..990]);
+        assert!(v[985..].iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            super::parse_spec("/nonexistent/disk"),
+            ("/nonexistent/disk", None, None)
+        );
+        assert_eq!(
+            super::parse_spec("/nonexistent/disk#s1a@ROOT"),
+            ("/nonexistent/disk", Some("s1a"), Some("ROOT"))
+        );
+        assert_eq!(
+            super::parse_spec("/nonexistent/disk#auto"),
+            ("/nonexistent/disk", Some("auto"), None)
+        );
+        assert_eq!(
+            super::parse_spec("/nonexistent/disk@offset=1024,size=4096@DATA"),
+            (
+                "/nonexistent/disk",
+                Some("offset=1024,size=4096"),
+                Some("DATA")
+            )
+        );
+        assert_eq!(
+            super::parse_spec("/nonexistent/disk@offset=1024"),
+            ("/nonexistent/disk", Some("offset=1024"), None)
+        );
+        // existing path containing '#' is not split
+        let f = TempFile::new("spec#p1", b"");
+        assert_eq!(super::parse_spec(&f.0), (f.0.as_str(), None, None));
+    }
+}
@@ -0,0 +1,109 @@
+// kernel page cache policy for open files
+pub(crate) const DEFAULT_THRESHOLD: u64 = 1024 * 1024;
+
+static NOTIFIER: std::sync::OnceLock<fuser::Notifier> = std::sync::OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum CacheMode {
+    #[default]
+    Keep,
+    Direct, // mmap(2) requires FUSE_DIRECT_IO_ALLOW_MMAP (Linux 6.6)
+    Adaptive,
+}
+
+impl CacheMode {
+    pub(crate) fn new(s: &str) -> nix::Result<Self> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "direct" => Ok(Self::Direct),
+            "adaptive" => Ok(Self::Adaptive),
+            _ => {
+                eprintln!("invalid cache mode {s}");
+                Err(nix::errno::Errno::EINVAL)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Policy {
+    mode: CacheMode,
+    threshold: u64,
+    has_datacache: bool,
+}
+
+impl Policy {
+    pub(crate) fn new(opt: &crate::option::Opt) -> Self {
+        Self {
+            mode: opt.cache_mode,
+            threshold: opt.cache_threshold.unwrap_or(DEFAULT_THRESHOLD),
+            has_datacache: !opt.nodatacache && opt.datacache_size != Some(0),
+        }
+    }
+
+    // adaptive keeps page cache for large files, and for all files
+    // without data cache, otherwise data cache serves the file
+    pub(crate) fn get_open_flags(&self, size: u64) -> u32 {
+        match self.mode {
+            CacheMode::Keep => fuser::consts::FOPEN_KEEP_CACHE,
+            CacheMode::Direct => fuser::consts::FOPEN_DIRECT_IO,
+            CacheMode::Adaptive => {
+                if !self.has_datacache || size >= self.threshold {
+                    fuser::consts::FOPEN_KEEP_CACHE
+                } else {
+                    fuser::consts::FOPEN_DIRECT_IO
+                }
+            }
+        }
+    }
+
+    pub(crate) fn get_opendir_flags(&self) -> u32 {
+        match self.mode {
+            CacheMode::Direct => 0,
+            _ => fuser::consts::FOPEN_KEEP_CACHE,
+        }
+    }
+}
+
+pub(crate) fn set_notifier(notifier: fuser::Notifier) {
+    if NOTIFIER.set(notifier).is_err() {
+        log::error!("notifier already set");
+    }
+}
+
+impl crate::Hammer2Fuse {
+    // notification from request thread may deadlock with kernel,
+    // hence invalidate in another thread
+    pub(crate) fn invalidate_inodes(&self) {
+        if self.cache.mode == CacheMode::Direct {
+            return;
+        }
+        let v = self.nlookup.keys().copied().collect();
+        self.invalidate(v, vec![]);
+    }
+
+    // inodes and (parent, name) entries in HAMMER2 inode numbers
+    pub(crate) fn invalidate(&self, inodes: Vec<u64>, entries: Vec<(u64, std::ffi::OsString)>) {
+        let Some(notifier) = NOTIFIER.get() else {
+            return;
+        };
+        let notifier = notifier.clone();
+        let inodes: Vec<_> = inodes.iter().map(|&x| self.get_fuse_inum(x)).collect();
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(x, name)| (self.get_fuse_inum(x), name))
+            .collect();
+        std::thread::spawn(move || {
+            for (dinum, name) in entries {
+                if let Err(e) = notifier.inval_entry(dinum, &name) {
+                    log::debug!("dinum {dinum} name {}: {e}", name.display());
+                }
+            }
+            for inum in inodes {
+                if let Err(e) = notifier.inval_inode(inum, 0, 0) {
+                    log::debug!("inum {inum}: {e}");
+                }
+            }
+        });
+    }
+}
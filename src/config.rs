@@ -0,0 +1,207 @@
+// TOML configuration file for mount defaults
+//
+// [global]
+// nodatacache = true
+//
+// [device."/dev/da0s1d@ROOT"]
+// attr_timeout = 5.0
+const CONFIG_NAME: &str = "hammer2-fuse.toml";
+
+// keys are option names, see option::OPTIONS
+pub(crate) type Section = toml::Table;
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    global: Section,
+    #[serde(default)]
+    device: std::collections::HashMap<String, Section>,
+}
+
+fn get_system_path() -> String {
+    if libfs::os::is_linux() {
+        format!("/etc/{CONFIG_NAME}")
+    } else {
+        format!("/usr/local/etc/{CONFIG_NAME}")
+    }
+}
+
+fn get_user_path() -> Option<String> {
+    let dir = std::env::var(crate::HAMMER2_HOME).ok()?;
+    if libfs::fs::is_dir(&dir) {
+        libfs::fs::join_path(&dir, &format!(".{CONFIG_NAME}"))
+    } else {
+        None
+    }
+}
+
+fn load_file(f: &str, required: bool) -> crate::Result<Option<Config>> {
+    let s = match std::fs::read_to_string(f) {
+        Ok(v) => v,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return Ok(None),
+        Err(e) => {
+            eprintln!("{f}: {e}");
+            return Err(Box::new(e));
+        }
+    };
+    let cfg: Config = match toml::from_str(&s) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{f}: {e}");
+            return Err(Box::new(e));
+        }
+    };
+    for k in std::iter::once(&cfg.global)
+        .chain(cfg.device.values())
+        .flat_map(toml::Table::keys)
+    {
+        if crate::option::find(k, crate::option::CONFIG).is_none()
+            && !crate::option::is_linux_only(k)
+        {
+            eprintln!("{f}: unknown option {k}");
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+    }
+    Ok(Some(cfg))
+}
+
+// sections of files in ascending order of precedence
+fn get_sections(l: Vec<Config>, spec: &str) -> Vec<Section> {
+    let special = match spec.split_once('@') {
+        Some((v, _)) => v,
+        None => spec,
+    };
+    let mut v = vec![];
+    for mut cfg in l {
+        v.push(std::mem::take(&mut cfg.global));
+        if let Some(x) = cfg.device.remove(special) {
+            v.push(x);
+        }
+        if special != spec
+            && let Some(x) = cfg.device.remove(spec)
+        {
+            v.push(x);
+        }
+    }
+    v
+}
+
+/// Returns applicable sections in ascending order of precedence.
+/// A system-wide file is overridden by `$HAMMER2_HOME` one unless `path` is given,
+/// and within a file `[global]` is overridden by `special` then `special@label`.
+pub(crate) fn load(path: Option<&str>, spec: &str) -> crate::Result<Vec<Section>> {
+    let paths = match path {
+        Some(v) => vec![v.to_string()],
+        None => [Some(get_system_path()), get_user_path()]
+            .into_iter()
+            .flatten()
+            .collect(),
+    };
+    let mut l = vec![];
+    for f in &paths {
+        if let Some(cfg) = load_file(f, path.is_some())? {
+            l.push(cfg);
+        }
+    }
+    Ok(get_sections(l, spec))
+}
+
+#[cfg(test)]
+mod tests {
+    const SYSTEM: &str = r#"
+[global]
+readahead = 4096
+nodatacache = true
+attr_timeout = 1
+
+[device."/dev/sdb1"]
+readahead = 8192
+casefold = true
+
+[device."/dev/sdb1@ROOT"]
+readahead = 16384
+cache = "direct"
+"#;
+
+    const USER: &str = r#"
+[global]
+log_rotate = 2
+
+[device."/dev/sdb1"]
+casefold = false
+"#;
+
+    fn get_opt(l: &[super::Section]) -> crate::option::Opt {
+        let mut opt = crate::option::Opt::default();
+        for x in l {
+            opt.apply_config(x).unwrap();
+        }
+        opt
+    }
+
+    fn get_sections(spec: &str) -> Vec<super::Section> {
+        let l = [SYSTEM, USER].map(|s| toml::from_str(s).unwrap());
+        super::get_sections(l.into(), spec)
+    }
+
+    #[test]
+    fn test_get_sections() {
+        // global, special, then special@label
+        let opt = get_opt(&get_sections("/dev/sdb1@ROOT"));
+        assert_eq!(opt.readahead, Some(16384));
+        assert_eq!(opt.cache_mode, crate::cache::CacheMode::Direct);
+        assert!(opt.nodatacache);
+        assert_eq!(opt.attr_timeout, Some(std::time::Duration::from_secs(1)));
+        // user file overrides system one
+        assert!(!opt.casefold);
+        assert_eq!(opt.log_rotate, Some(2));
+        let opt = get_opt(&get_sections("/dev/sdb1"));
+        assert_eq!(opt.readahead, Some(8192));
+        assert_eq!(opt.cache_mode, crate::cache::CacheMode::default());
+        let opt = get_opt(&get_sections("/dev/sdb1@DATA"));
+        assert_eq!(opt.readahead, Some(8192));
+        let opt = get_opt(&get_sections("/dev/sdc1"));
+        assert_eq!(opt.readahead, Some(4096));
+        assert!(!opt.casefold);
+        // system file only
+        let l = super::get_sections(vec![toml::from_str(SYSTEM).unwrap()], "/dev/sdb1");
+        assert!(get_opt(&l).casefold);
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut opt = crate::option::Opt::default();
+        for s in [
+            "readahead = true",
+            "readahead = \"x\"",
+            "allow_other = 1",
+            "allow_other = [true]",
+            "fsname = \"x\"",
+        ] {
+            let x: super::Section = toml::from_str(s).unwrap();
+            assert!(opt.apply_config(&x).is_err(), "{s}");
+        }
+        let x = toml::from_str("salvage = false\nlost_found = true\n").unwrap();
+        assert!(opt.apply_config(&x).is_ok());
+        assert_eq!(opt.salvage, crate::salvage::SalvageMode::Off);
+        assert!(opt.lost_found);
+    }
+
+    #[test]
+    fn test_load() {
+        let f = std::env::temp_dir().join(format!("hammer2-fuse-config.{}", std::process::id()));
+        let f = f.to_str().unwrap();
+        // only given file, required
+        std::fs::write(f, USER).unwrap();
+        let l = super::load(Some(f), "/dev/sdb1@ROOT").unwrap();
+        assert_eq!(l.len(), 2);
+        assert!(!get_opt(&l).casefold);
+        std::fs::write(f, "[global]\nunknown = true\n").unwrap();
+        assert!(super::load(Some(f), "/dev/sdb1").is_err());
+        std::fs::write(f, "[other]\n").unwrap();
+        assert!(super::load(Some(f), "/dev/sdb1").is_err());
+        std::fs::remove_file(f).unwrap();
+        assert!(super::load(Some(f), "/dev/sdb1").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use libhammer2::ErrorExt;
+
+// decompressed file data cache with LRU eviction, bounded by bytes
+pub(crate) const BLOCK_SIZE: u64 = 65536;
+
+type Key = (u64, u64); // inum, block offset
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct DataCache {
+    max_size: u64,
+    size: u64,
+    tick: u64,
+    map: std::collections::HashMap<Key, (u64, Vec<u8>)>,
+    lru: std::collections::BTreeMap<u64, Key>,
+    index: std::collections::HashMap<u64, std::collections::HashSet<u64>>, // inum to blocks
+    stats: Stats,
+}
+
+impl DataCache {
+    pub(crate) fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            size: 0,
+            tick: 0,
+            map: std::collections::HashMap::new(),
+            lru: std::collections::BTreeMap::new(),
+            index: std::collections::HashMap::new(),
+            stats: Stats::default(),
+        }
+    }
+
+    pub(crate) fn get_stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    // percent
+    pub(crate) fn get_hit_rate(&self) -> u64 {
+        let total = self.stats.hits.saturating_add(self.stats.misses);
+        self.stats
+            .hits
+            .saturating_mul(100)
+            .checked_div(total)
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.size = 0;
+        self.map.clear();
+        self.lru.clear();
+        self.index.clear();
+    }
+
+    pub(crate) fn remove_inode(&mut self, inum: u64) -> libhammer2::Result<()> {
+        let Some(l) = self.index.remove(&inum) else {
+            return Ok(());
+        };
+        for boff in l {
+            if let Some((t, v)) = self.map.remove(&(inum, boff)) {
+                self.lru.remove(&t);
+                self.size -= u64::try_from(v.len()).or_range()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_index(&mut self, key: Key) {
+        if let Some(l) = self.index.get_mut(&key.0) {
+            l.remove(&key.1);
+            if l.is_empty() {
+                self.index.remove(&key.0);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: Key) -> bool {
+        self.tick += 1;
+        let Some(v) = self.map.get_mut(&key) else {
+            return false;
+        };
+        self.lru.remove(&v.0);
+        v.0 = self.tick;
+        self.lru.insert(self.tick, key);
+        true
+    }
+
+    fn insert(&mut self, key: Key, buf: Vec<u8>) -> libhammer2::Result<()> {
+        let n = u64::try_from(buf.len()).or_range()?;
+        while self.size + n > self.max_size {
+            let Some((_, k)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some((_, v)) = self.map.remove(&k) {
+                self.size -= u64::try_from(v.len()).or_range()?;
+                self.stats.evictions += 1;
+                self.remove_index(k);
+            }
+        }
+        self.tick += 1;
+        self.size += n;
+        self.lru.insert(self.tick, key);
+        self.index.entry(key.0).or_default().insert(key.1);
+        if let Some((t, v)) = self.map.insert(key, (self.tick, buf)) {
+            self.lru.remove(&t);
+            self.size -= u64::try_from(v.len()).or_range()?;
+        }
+        Ok(())
+    }
+
+    // f reads size bytes at offset, returning less at EOF of fsize
+    pub(crate) fn read<F>(
+        &mut self,
+        inum: u64,
+        size: u64,
+        offset: u64,
+        fsize: u64,
+        mut f: F,
+    ) -> libhammer2::Result<Vec<u8>>
+    where
+        F: FnMut(u64, u64) -> libhammer2::Result<Vec<u8>>,
+    {
+        if self.max_size < BLOCK_SIZE {
+            return f(size, offset);
+        }
+        let end = std::cmp::min(offset.saturating_add(size), fsize);
+        if offset >= end {
+            return Ok(vec![]);
+        }
+        let mut v = Vec::with_capacity((end - offset).try_into().or_range()?);
+        let mut boff = offset / BLOCK_SIZE * BLOCK_SIZE;
+        while boff < end {
+            let key = (inum, boff);
+            if self.touch(key) {
+                self.stats.hits += 1;
+            } else {
+                self.stats.misses += 1;
+                self.insert(key, f(BLOCK_SIZE, boff)?)?;
+            }
+            let buf = &self.map[&key].1;
+            let beg = usize::try_from(boff.max(offset) - boff).or_range()?;
+            let stop = std::cmp::min(usize::try_from(end - boff).or_range()?, buf.len());
+            if beg >= stop {
+                break;
+            }
+            v.extend_from_slice(&buf[beg..stop]);
+            if u64::try_from(buf.len()).or_range()? < BLOCK_SIZE {
+                break; // EOF
+            }
+            boff += BLOCK_SIZE;
+        }
+        Ok(v)
+    }
+}
+
+pub(crate) fn pread(
+    pmp: &mut libhammer2::hammer2::Hammer2,
+    dc: &mut Option<DataCache>,
+    inum: u64,
+    size: u64,
+    offset: u64,
+) -> libhammer2::Result<Vec<u8>> {
+    match dc {
+        Some(dc) => {
+            let fsize = match pmp.get_inode(inum) {
+                Some(ip) => ip.get_meta().size,
+                None => pmp.stat(inum)?.st_size,
+            };
+            dc.read(inum, size, offset, fsize, |size, offset| {
+                pmp.preadx(inum, size, offset)
+            })
+        }
+        None => pmp.preadx(inum, size, offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    const BS: u64 = super::BLOCK_SIZE;
+
+    // inum in first byte of each block, records block offsets read
+    fn reader(
+        inum: u64,
+        fsize: u64,
+        reads: &mut Vec<u64>,
+    ) -> impl FnMut(u64, u64) -> libhammer2::Result<Vec<u8>> + '_ {
+        move |size, offset| {
+            reads.push(offset);
+            let n = std::cmp::min(offset.saturating_add(size), fsize).saturating_sub(offset);
+            let mut v = vec![0; usize::try_from(n).unwrap()];
+            if let Some(x) = v.first_mut() {
+                *x = u8::try_from(inum).unwrap();
+            }
+            Ok(v)
+        }
+    }
+
+    #[test]
+    fn test_hit() {
+        let mut dc = super::DataCache::new(4 * BS);
+        let mut reads = vec![];
+        let v = dc
+            .read(1, 100, BS + 10, 4 * BS, reader(1, 4 * BS, &mut reads))
+            .unwrap();
+        assert_eq!(v.len(), 100);
+        let v = dc
+            .read(1, 10, BS, 4 * BS, reader(1, 4 * BS, &mut reads))
+            .unwrap();
+        assert_eq!(v[0], 1);
+        assert_eq!(reads, [BS]);
+        assert_eq!(dc.get_stats().hits, 1);
+        assert_eq!(dc.get_stats().misses, 1);
+        // spans two blocks
+        let v = dc
+            .read(1, 20, 2 * BS - 10, 4 * BS, reader(1, 4 * BS, &mut reads))
+            .unwrap();
+        assert_eq!(v.len(), 20);
+        assert_eq!(reads, [BS, 2 * BS]);
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut dc = super::DataCache::new(2 * BS);
+        let mut reads = vec![];
+        for offset in [0, BS, 0, 2 * BS] {
+            dc.read(1, 1, offset, 4 * BS, reader(1, 4 * BS, &mut reads))
+                .unwrap();
+        }
+        // BS evicted as least recently used, 0 kept
+        assert_eq!(dc.get_stats().evictions, 1);
+        dc.read(1, 1, 0, 4 * BS, reader(1, 4 * BS, &mut reads))
+            .unwrap();
+        assert_eq!(reads, [0, BS, 2 * BS]);
+        dc.read(1, 1, BS, 4 * BS, reader(1, 4 * BS, &mut reads))
+            .unwrap();
+        assert_eq!(reads, [0, BS, 2 * BS, BS]);
+        assert!(dc.size <= 2 * BS);
+    }
+
+    #[test]
+    fn test_eof() {
+        let mut dc = super::DataCache::new(4 * BS);
+        let mut reads = vec![];
+        let v = dc
+            .read(1, 3 * BS, 0, BS + 5, reader(1, BS + 5, &mut reads))
+            .unwrap();
+        assert_eq!(v.len(), usize::try_from(BS + 5).unwrap());
+        assert_eq!(reads, [0, BS]);
+        let v = dc
+            .read(1, u64::MAX, 1, BS + 5, reader(1, BS + 5, &mut reads))
+            .unwrap();
+        assert_eq!(v.len(), usize::try_from(BS + 4).unwrap());
+        assert!(
+            dc.read(1, 10, BS + 5, BS + 5, reader(1, BS + 5, &mut reads))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_remove_inode() {
+        let mut dc = super::DataCache::new(8 * BS);
+        let mut reads = vec![];
+        for inum in [1, 2] {
+            dc.read(inum, 2 * BS, 0, 4 * BS, reader(inum, 4 * BS, &mut reads))
+                .unwrap();
+        }
+        assert_eq!(dc.size, 4 * BS);
+        dc.remove_inode(1).unwrap();
+        assert_eq!(dc.size, 2 * BS);
+        assert_eq!(dc.map.len(), 2);
+        assert_eq!(dc.lru.len(), 2);
+        assert!(!dc.index.contains_key(&1));
+        assert_eq!(dc.index[&2].len(), 2);
+        dc.remove_inode(3).unwrap();
+        let v = dc
+            .read(2, 1, 0, 4 * BS, reader(2, 4 * BS, &mut reads))
+            .unwrap();
+        assert_eq!(v, [2]);
+        assert_eq!(reads.len(), 4);
+        dc.clear();
+        assert_eq!(dc.size, 0);
+        assert!(dc.index.is_empty());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut dc = super::DataCache::new(BS - 1);
+        let mut reads = vec![];
+        dc.read(1, 10, 5, BS, reader(1, BS, &mut reads)).unwrap();
+        dc.read(1, 10, 5, BS, reader(1, BS, &mut reads)).unwrap();
+        assert_eq!(reads, [5, 5]);
+    }
+}
@@ -0,0 +1,199 @@
+// byte exact directory entry names, libhammer2 takes and returns String
+
+// inum, type and name, None if name not in chain data
+pub(crate) type ChainDirent = (u64, u8, Option<Vec<u8>>);
+
+fn get_inode_dirent(ipdata: &libhammer2::fs::Hammer2InodeData) -> nix::Result<ChainDirent> {
+    let n = usize::from(ipdata.meta.name_len);
+    let name = ipdata.filename.get(..n).ok_or(nix::errno::Errno::EINVAL)?;
+    Ok((ipdata.meta.inum, ipdata.meta.typ, Some(name.to_vec())))
+}
+
+// short name is in check field, long one in data block
+fn get_bref_dirent<'a>(
+    bref: &libhammer2::fs::Hammer2Blockref,
+    data: impl FnOnce() -> &'a [u8],
+) -> ChainDirent {
+    let head: &libhammer2::fs::Hammer2DirentHead = libfs::cast::align_to(&bref.embed);
+    let n = usize::from(head.namlen);
+    let name = if n <= libhammer2::fs::HAMMER2_DIRENT_INLINE_NAMELEN {
+        bref.check.get(..n)
+    } else {
+        data().get(..n)
+    };
+    (head.inum, head.typ, name.map(<[u8]>::to_vec))
+}
+
+pub(crate) fn get_chain_dirent(
+    chain: &libhammer2::chain::Chain,
+) -> nix::Result<Option<ChainDirent>> {
+    let bref = chain.get_blockref();
+    match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            Ok(Some(get_inode_dirent(chain.as_inode_data())?))
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT => {
+            Ok(Some(get_bref_dirent(bref, || chain.get_data())))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub(crate) fn lookup_bytes(
+    pmp: &mut libhammer2::hammer2::Hammer2,
+    dinum: u64,
+    name: &[u8],
+) -> libhammer2::Result<u64> {
+    let dcid = pmp.get_inode_chain(dinum, libhammer2::hammer2::RESOLVE_ALWAYS)?;
+    if dcid == libhammer2::chain::CID_NONE {
+        return Err(nix::errno::Errno::EIO.into());
+    }
+    let lhc = libhammer2::subs::dirhash(name);
+    let key_end = lhc + libhammer2::fs::HAMMER2_DIRHASH_LOMASK;
+    let (mut pcid, mut cid, _) = pmp.lookup_chain(dcid, lhc, key_end, 0)?;
+    while cid != libhammer2::chain::CID_NONE {
+        let chain = pmp.get_chain(cid).ok_or(nix::errno::Errno::ENOENT)?;
+        if chain.match_name_from_bytes(name)
+            && let Some((inum, _, _)) = get_chain_dirent(chain)?
+        {
+            return Ok(inum);
+        }
+        (pcid, cid, _) = pmp.get_next_chain(pcid, cid, key_end, 0)?;
+    }
+    Err(nix::errno::Errno::ENOENT.into())
+}
+
+impl crate::Hammer2Fuse {
+    pub(crate) fn lookup_bytes(&mut self, dinum: u64, name: &[u8]) -> libhammer2::Result<u64> {
+        lookup_bytes(&mut self.pmp, dinum, name)
+    }
+
+    // inum to raw names of entries
+    fn get_raw_names(
+        &mut self,
+        dinum: u64,
+    ) -> libhammer2::Result<std::collections::HashMap<u64, Vec<Vec<u8>>>> {
+        let mut m = std::collections::HashMap::<_, Vec<_>>::new();
+        let dcid = self
+            .pmp
+            .get_inode_chain(dinum, libhammer2::hammer2::RESOLVE_ALWAYS)?;
+        if dcid == libhammer2::chain::CID_NONE {
+            return Err(nix::errno::Errno::EIO.into());
+        }
+        let key_beg = libhammer2::fs::HAMMER2_DIRHASH_VISIBLE;
+        let key_end = libhammer2::fs::HAMMER2_KEY_MAX;
+        let (mut pcid, mut cid, _) = self.pmp.lookup_chain(dcid, key_beg, key_end, 0)?;
+        while cid != libhammer2::chain::CID_NONE {
+            if let Some((inum, _, Some(name))) = get_chain_dirent(self.get_chain(cid)?)? {
+                m.entry(inum).or_default().push(name);
+            }
+            (pcid, cid, _) = self.pmp.get_next_chain(pcid, cid, key_end, 0)?;
+        }
+        Ok(m)
+    }
+
+    // replace names lossily converted by libhammer2
+    pub(crate) fn get_dirent_names(
+        &mut self,
+        dinum: u64,
+        v: &[libhammer2::hammer2::Dirent],
+    ) -> Vec<std::ffi::OsString> {
+        let mut l: Vec<_> = v.iter().map(|e| e.name.clone().into()).collect();
+        if !v
+            .iter()
+            .any(|e| e.name.contains(char::REPLACEMENT_CHARACTER))
+        {
+            return l;
+        }
+        let m = match self.get_raw_names(dinum) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("dinum {dinum}: {e}");
+                return l;
+            }
+        };
+        for (i, e) in v.iter().enumerate() {
+            if !e.name.contains(char::REPLACEMENT_CHARACTER) {
+                continue;
+            }
+            if let Some(x) = m.get(&e.inum).and_then(|x| {
+                x.iter()
+                    .find(|x| String::from_utf8_lossy(x) == e.name.as_str())
+            }) {
+                l[i] = std::os::unix::ffi::OsStringExt::from_vec(x.clone());
+            }
+        }
+        l
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn get_bref(inum: u64, typ: u8, name: &[u8]) -> libhammer2::fs::Hammer2Blockref {
+        let b = vec![0; libhammer2::fs::HAMMER2_BLOCKREF_BYTES];
+        let mut bref = *libfs::cast::align_to::<libhammer2::fs::Hammer2Blockref>(&b);
+        bref.typ = libhammer2::fs::HAMMER2_BREF_TYPE_DIRENT;
+        let mut head = *libfs::cast::align_to::<libhammer2::fs::Hammer2DirentHead>(&bref.embed);
+        head.inum = inum;
+        head.namlen = u16::try_from(name.len()).unwrap();
+        head.typ = typ;
+        bref.embed.copy_from_slice(libfs::cast::as_u8_slice(&head));
+        if name.len() <= libhammer2::fs::HAMMER2_DIRENT_INLINE_NAMELEN {
+            bref.check[..name.len()].copy_from_slice(name);
+        }
+        bref
+    }
+
+    #[test]
+    fn test_get_bref_dirent() {
+        let typ = libhammer2::fs::HAMMER2_OBJTYPE_REGFILE;
+        // non-UTF-8 short name, data block not read
+        let name = b"a\xff\xfeb";
+        let bref = get_bref(100, typ, name);
+        assert_eq!(
+            super::get_bref_dirent(&bref, || unreachable!()),
+            (100, typ, Some(name.to_vec()))
+        );
+        let name = [b'x'; libhammer2::fs::HAMMER2_DIRENT_INLINE_NAMELEN];
+        let bref = get_bref(101, typ, &name);
+        assert_eq!(
+            super::get_bref_dirent(&bref, || unreachable!()),
+            (101, typ, Some(name.to_vec()))
+        );
+        // long name in data block
+        let name = [b'y'; libhammer2::fs::HAMMER2_DIRENT_INLINE_NAMELEN + 1];
+        let bref = get_bref(102, typ, &name);
+        let data = [&name[..], &[0; 7]].concat();
+        assert_eq!(
+            super::get_bref_dirent(&bref, || &data),
+            (102, typ, Some(name.to_vec()))
+        );
+        assert_eq!(
+            super::get_bref_dirent(&bref, || &name[..10]),
+            (102, typ, None)
+        );
+    }
+
+    #[test]
+    fn test_get_inode_dirent() {
+        let b = vec![0; std::mem::size_of::<libhammer2::fs::Hammer2InodeData>()];
+        let mut ipdata = *libfs::cast::align_to::<libhammer2::fs::Hammer2InodeData>(&b);
+        ipdata.meta.inum = 100;
+        ipdata.meta.typ = libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY;
+        ipdata.meta.name_len = 3;
+        ipdata.filename[..3].copy_from_slice(b"d\xffr");
+        assert_eq!(
+            super::get_inode_dirent(&ipdata),
+            Ok((
+                100,
+                libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY,
+                Some(b"d\xffr".to_vec())
+            ))
+        );
+        ipdata.meta.name_len = 257;
+        assert_eq!(
+            super::get_inode_dirent(&ipdata),
+            Err(nix::errno::Errno::EINVAL)
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use unicode_normalization::UnicodeNormalization;
+
+// lookup fallback by case folded and / or NFC normalized names,
+// index per directory built on first miss
+#[derive(Debug, Default)]
+pub(crate) struct Fold {
+    casefold: bool,
+    normalize: bool,
+    index: std::collections::HashMap<u64, std::collections::HashMap<String, u64>>,
+}
+
+impl Fold {
+    pub(crate) fn new(opt: &crate::option::Opt) -> Self {
+        Self {
+            casefold: opt.casefold,
+            normalize: opt.normalize,
+            index: std::collections::HashMap::new(),
+        }
+    }
+
+    fn get_key(&self, name: &str) -> String {
+        let s = if self.normalize {
+            name.nfc().collect()
+        } else {
+            name.to_string()
+        };
+        if self.casefold { s.to_lowercase() } else { s }
+    }
+
+    pub(crate) fn remove(&mut self, dinum: u64) {
+        self.index.remove(&dinum);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.index.clear();
+    }
+}
+
+impl crate::Hammer2Fuse {
+    pub(crate) fn is_fold_enabled(&self) -> bool {
+        self.fold.casefold || self.fold.normalize
+    }
+
+    pub(crate) fn lookup_fold(
+        &mut self,
+        dinum: u64,
+        name: &std::ffi::OsStr,
+    ) -> libhammer2::Result<u64> {
+        let Some(name) = name.to_str() else {
+            return Err(nix::errno::Errno::ENOENT.into());
+        };
+        if !self.fold.index.contains_key(&dinum) {
+            let v = self.pmp.readdir(dinum)?;
+            let names = self.get_dirent_names(dinum, &v);
+            let mut m = std::collections::HashMap::new();
+            for (e, x) in v.iter().zip(&names).skip(2) {
+                // "." and ".."
+                let Some(x) = x.to_str() else {
+                    continue;
+                };
+                let k = self.fold.get_key(x);
+                if let Some(inum) = m.get(&k) {
+                    log::debug!("dinum {dinum}: {x} folds to inum {inum}");
+                    continue;
+                }
+                m.insert(k, e.inum);
+            }
+            self.fold.index.insert(dinum, m);
+        }
+        let k = self.fold.get_key(name);
+        match self.fold.index.get(&dinum).and_then(|m| m.get(&k)) {
+            Some(&v) => Ok(v),
+            None => Err(nix::errno::Errno::ENOENT.into()),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+// --follow remounts when volume header with newer mirror_tid appears,
+// e.g. image of a running VM
+pub(crate) const DEFAULT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+static CHANGED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[derive(Debug)]
+pub(crate) struct Follow {
+    spec: String,
+    subdir: Option<String>,
+    mopt: Vec<String>,
+    stale: std::collections::HashSet<u64>, // handles of gone inodes until released
+}
+
+impl Follow {
+    pub(crate) fn new(spec: &str, subdir: Option<&str>, mopt: &[&str]) -> Self {
+        Self {
+            spec: spec.to_string(),
+            subdir: subdir.map(ToString::to_string),
+            mopt: mopt.iter().map(ToString::to_string).collect(),
+            stale: std::collections::HashSet::new(),
+        }
+    }
+}
+
+fn get_mirror_tid(src: &mut dyn crate::blksrc::BlockSource) -> std::io::Result<Option<u64>> {
+    let (hdrs, best) = crate::probe::read_headers(src)?;
+    Ok(best.and_then(|i| hdrs[i].as_ref().map(|x| x.mirror_tid)))
+}
+
+// polls volume 0, must be called after daemonize
+pub(crate) fn spawn(special: &str, selector: Option<&str>, interval: std::time::Duration) {
+    let special = special.split(':').next().unwrap_or_default().to_string();
+    let selector = selector.map(ToString::to_string);
+    std::thread::spawn(move || {
+        let mut src = match crate::blksrc::open_raw(&special, selector.as_deref()) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{special}: {e}");
+                return;
+            }
+        };
+        let mut tid = get_mirror_tid(&mut *src).ok().flatten().unwrap_or(0);
+        log::info!("following {special} from mirror_tid {tid:#x}");
+        loop {
+            std::thread::sleep(interval);
+            match get_mirror_tid(&mut *src) {
+                Ok(Some(v)) if v > tid => {
+                    log::info!("mirror_tid {tid:#x} -> {v:#x}");
+                    tid = v;
+                    CHANGED.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                Ok(_) => (),
+                Err(e) => log::error!("{special}: {e}"),
+            }
+        }
+    });
+}
+
+impl crate::Hammer2Fuse {
+    // called with MTX held, by requests taking a new reference or
+    // reading via one
+    pub(crate) fn try_follow(&mut self) {
+        if !CHANGED.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self.remount() {
+            log::error!("remount: {e}");
+            CHANGED.store(true, std::sync::atomic::Ordering::Relaxed); // retry
+        }
+    }
+
+    fn resolve_path(
+        &self,
+        pmp: &mut libhammer2::hammer2::Hammer2,
+        inum: u64,
+    ) -> libhammer2::Result<u64> {
+        let mut v = vec![];
+        let mut x = inum;
+        while x != self.root {
+            let Some((dinum, name)) = self.names.get(&x) else {
+                return Err(nix::errno::Errno::ENOENT.into());
+            };
+            v.push(name.clone());
+            x = *dinum;
+        }
+        let mut x = self.root;
+        for name in v.iter().rev() {
+            x = match name.to_str() {
+                Some(s) => pmp.nresolve(x, s)?,
+                None => crate::dirent::lookup_bytes(
+                    pmp,
+                    x,
+                    std::os::unix::ffi::OsStrExt::as_bytes(&**name),
+                )?,
+            };
+        }
+        Ok(x)
+    }
+
+    // reload inodes known to kernel or open by path, and take references
+    // on new mount, returns reloaded and gone inodes
+    fn reload(
+        &self,
+        pmp: &mut libhammer2::hammer2::Hammer2,
+    ) -> crate::Result<(Vec<u64>, Vec<u64>)> {
+        if let Some(v) = self.follow.as_ref().and_then(|f| f.subdir.as_deref())
+            && crate::subdir::resolve(pmp, v)? != self.root
+        {
+            return Err(Box::new(nix::errno::Errno::ESTALE));
+        }
+        let l = self.get_ref_inodes();
+        let mut inodes = vec![self.root];
+        let mut gone = vec![];
+        for &inum in &l {
+            if inum == self.root || crate::lostfound::is_virtual(inum) {
+                continue;
+            }
+            match self.resolve_path(pmp, inum) {
+                Ok(x) if x == inum => inodes.push(inum),
+                _ => {
+                    log::debug!("inum {inum} gone");
+                    gone.push(inum);
+                }
+            }
+        }
+        let l: Vec<_> = l.into_iter().filter(|x| !gone.contains(x)).collect();
+        crate::handle::get_refs(pmp, &l)?;
+        Ok((inodes, gone))
+    }
+
+    fn remount(&mut self) -> crate::Result<()> {
+        let Some(f) = &self.follow else {
+            return Ok(());
+        };
+        let mopt: Vec<_> = f.mopt.iter().map(String::as_str).collect();
+        let mut pmp = crate::mount_hammer2(&f.spec, &mopt, false)?;
+        let (inodes, gone) = match self.reload(&mut pmp) {
+            Ok(v) => v,
+            Err(e) => {
+                if let Err(e) = pmp.unmount() {
+                    log::error!("{e}");
+                }
+                return Err(e);
+            }
+        };
+        let mut entries = vec![];
+        for inum in &gone {
+            if let Some((dinum, name)) = self.names.remove(inum) {
+                entries.push((dinum, name));
+            }
+        }
+        let mut old = std::mem::replace(&mut self.pmp, pmp);
+        if let Err(e) = old.unmount() {
+            log::error!("{e}");
+        }
+
+        if let Some(dc) = &mut self.datacache {
+            dc.clear();
+        }
+        self.handles.clear_readahead();
+        self.fold.clear();
+        self.prune.reset();
+        log::info!(
+            "remounted, {} inodes reloaded, {} entries gone",
+            inodes.len(),
+            entries.len()
+        );
+        self.invalidate(inodes, entries);
+        // no reference taken on new mount, kernel gets ESTALE
+        for inum in &gone {
+            self.nlookup.remove(inum);
+        }
+        for fh in self.handles.remove_inodes(&gone) {
+            if let Some(f) = &mut self.follow {
+                f.stale.insert(fh);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_stale_handle(&self, fh: u64) -> bool {
+        self.follow.as_ref().is_some_and(|f| f.stale.contains(&fh))
+    }
+
+    // true if fh was stale
+    pub(crate) fn put_stale_handle(&mut self, fh: u64) -> bool {
+        self.follow.as_mut().is_some_and(|f| f.stale.remove(&fh))
+    }
+}
@@ -1,12 +1,18 @@
 use libhammer2::ErrorExt;
 use libhammer2::OptionExt;
 
+macro_rules! reply_error {
+    ($reply:expr_2021, $errno:expr_2021) => {
+        $reply.error(crate::reqlog::set_errno($errno))
+    };
+}
+
 macro_rules! try_into {
     ($x:expr_2021, $reply:expr_2021) => {
         match $x.try_into().or_range() {
             Ok(v) => v,
             Err(e) => {
-                $reply.error(h2i(&e));
+                reply_error!($reply, h2i(&e));
                 return;
             }
         }
@@ -18,7 +24,7 @@ macro_rules! try_inode_mut {
         match $self.pmp.get_inode_mut($inum).or_range() {
             Ok(v) => v,
             Err(e) => {
-                $reply.error(h2i(&e));
+                reply_error!($reply, h2i(&e));
                 return;
             }
         }
@@ -28,7 +34,7 @@ macro_rules! try_inode_mut {
 macro_rules! try_inode_mut_get {
     ($self:expr_2021, $inum:expr_2021, $reply:expr_2021) => {
         if let Err(e) = try_inode_mut!($self, $inum, $reply).get() {
-            $reply.error(h2i(&e.into()));
+            reply_error!($reply, h2i(&e.into()));
             return;
         }
     };
@@ -37,9 +43,20 @@ macro_rules! try_inode_mut_get {
 macro_rules! try_inode_mut_put {
     ($self:expr_2021, $inum:expr_2021, $reply:expr_2021) => {
         if let Err(e) = try_inode_mut!($self, $inum, $reply).put() {
-            $reply.error(h2i(&e.into()));
+            reply_error!($reply, h2i(&e.into()));
+            return;
+        }
+    };
+}
+
+// handles of inodes gone after --follow remount get ESTALE
+macro_rules! check_handle {
+    ($self:expr_2021, $fh:expr_2021, $inum:expr_2021, $reply:expr_2021) => {
+        if $self.is_stale_handle($fh) {
+            reply_error!($reply, libc::ESTALE);
             return;
         }
+        assert_eq!($self.handles.get_inum($fh), Some($inum));
     };
 }
 
@@ -51,7 +68,7 @@ macro_rules! try_mtx_lock {
         match $mtx.lock() {
             Ok(v) => v,
             Err(_) => {
-                $reply.error(nix::errno::Errno::EFAULT as i32);
+                reply_error!($reply, nix::errno::Errno::EFAULT as i32);
                 return;
             }
         }
@@ -59,20 +76,36 @@ macro_rules! try_mtx_lock {
 }
 
 macro_rules! try_stat2attr {
-    ($st:expr_2021, $reply:expr_2021) => {
-        match crate::util::stat2attr($st) {
-            Ok(v) => {
+    ($self:expr_2021, $st:expr_2021, $reply:expr_2021) => {
+        match crate::util::stat2attr(
+            $st,
+            $self.pmp.get_inode($st.st_ino).map(|ip| ip.get_meta()),
+            &$self.idmap,
+        ) {
+            Ok(mut v) => {
+                v.ino = $self.get_fuse_inum(v.ino);
                 log::debug!("{v:?}");
                 v
             }
             Err(e) => {
-                $reply.error(e as i32);
+                reply_error!($reply, e as i32);
                 return;
             }
         }
     };
 }
 
+macro_rules! try_access {
+    ($self:expr_2021, $req:expr_2021, $inum:expr_2021, $mask:expr_2021, $reply:expr_2021) => {
+        if !$self.default_permissions
+            && let Err(e) = $self.check_access($req, $inum, $mask)
+        {
+            reply_error!($reply, h2i(&e));
+            return;
+        }
+    };
+}
+
 macro_rules! debug_req {
     ($req:expr_2021, $cond:expr_2021) => {
         if $cond {
@@ -81,7 +114,7 @@ macro_rules! debug_req {
     };
 }
 
-const TTL: std::time::Duration = std::time::Duration::from_secs(1);
+pub(crate) const TTL: std::time::Duration = std::time::Duration::from_secs(1);
 
 fn h2i(e: &libhammer2::Error) -> i32 {
     (match e {
@@ -112,6 +145,17 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         log::debug!("destroy");
         let _mtx = MTX.lock().unwrap();
         assert_eq!(self.total_open, 0);
+        self.put_lookup_all();
+        if let Some(dc) = &self.datacache {
+            let st = dc.get_stats();
+            log::info!(
+                "datacache hits {} misses {} evictions {} hit rate {}%",
+                st.hits,
+                st.misses,
+                st.evictions,
+                dc.get_hit_rate()
+            );
+        }
         if let Err(e) = self.pmp.unmount() {
             log::error!("{e}");
         }
@@ -125,25 +169,82 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyEntry,
     ) {
         debug_req!(req, self.debug > 1);
+        let dinum = self.get_h2_inum(dinum);
+        let _rec = crate::reqlog::Record::new(req, "lookup", dinum);
         log::debug!("dinum {dinum} name {}", name.display());
         let _mtx = try_mtx_lock!(MTX, reply);
-        let Some(name) = name.to_str() else {
-            reply.error(libc::EINVAL);
-            return;
+        self.try_follow();
+        try_access!(self, req, dinum, libc::X_OK, reply);
+        let ret = match self.lookup_lost_found(dinum, name) {
+            Some(v) => v,
+            None => match name.to_str() {
+                Some("..") if dinum == self.root => Ok(dinum), // stay inside
+                Some(s) => self.pmp.nresolve(dinum, s),
+                None => self.lookup_bytes(dinum, std::os::unix::ffi::OsStrExt::as_bytes(name)),
+            },
+        };
+        let ret = match ret {
+            Err(e)
+                if self.is_fold_enabled()
+                    && !crate::lostfound::is_virtual(dinum)
+                    && h2i(&e) == libc::ENOENT =>
+            {
+                self.lookup_fold(dinum, name)
+            }
+            _ => ret,
         };
-        let inum = match self.pmp.nresolve(dinum, name) {
+        let inum = match ret {
             Ok(v) => v,
             Err(e) => {
-                reply.error(h2i(&e));
+                reply_error!(reply, h2i(&e));
                 return;
             }
         };
+        // not counted, never forgotten
+        if crate::lostfound::is_virtual(inum) {
+            match self.get_lost_found_attr(inum) {
+                Ok(v) => reply.entry(&self.entry_ttl, &v, 0),
+                Err(e) => reply_error!(reply, h2i(&e)),
+            }
+            return;
+        }
+        self.prune.add_lookup();
         match self.pmp.stat(inum) {
             Ok(v) => {
-                let attr = try_stat2attr!(&v, reply);
-                reply.entry(&TTL, &attr, 0);
+                let attr = try_stat2attr!(self, &v, reply);
+                // reply.entry() increments kernel lookup count
+                if let Err(e) = self.inc_lookup(inum) {
+                    reply_error!(reply, h2i(&e));
+                    return;
+                }
+                self.cache_name(dinum, name, inum);
+                reply.entry(&self.entry_ttl, &attr, 0);
             }
-            Err(e) => reply.error(h2i(&e)),
+            Err(e) => reply_error!(reply, h2i(&e)),
+        }
+        self.try_prune();
+    }
+
+    fn forget(&mut self, req: &fuser::Request<'_>, inum: u64, nlookup: u64) {
+        debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "forget", inum);
+        log::debug!("inum {inum} nlookup {nlookup}");
+        let Ok(_mtx) = MTX.lock() else {
+            return;
+        };
+        self.dec_lookup(inum, nlookup);
+    }
+
+    fn batch_forget(&mut self, req: &fuser::Request<'_>, nodes: &[fuser::fuse_forget_one]) {
+        debug_req!(req, self.debug > 1);
+        let _rec = crate::reqlog::Record::new(req, "batch_forget", 0);
+        log::debug!("nodes {}", nodes.len());
+        let Ok(_mtx) = MTX.lock() else {
+            return;
+        };
+        for x in nodes {
+            self.dec_lookup(self.get_h2_inum(x.nodeid), x.nlookup);
         }
     }
 
@@ -155,41 +256,79 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyAttr,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "getattr", inum);
         log::debug!("inum {inum}");
         let _mtx = try_mtx_lock!(MTX, reply);
+        self.try_follow();
         if let Some(fh) = fh {
-            assert_eq!(inum, fh);
+            check_handle!(self, fh, inum, reply);
+        }
+        if crate::lostfound::is_virtual(inum) {
+            match self.get_lost_found_attr(inum) {
+                Ok(v) => reply.attr(&self.attr_ttl, &v),
+                Err(e) => reply_error!(reply, h2i(&e)),
+            }
+            return;
         }
         match self.pmp.stat(inum) {
             Ok(v) => {
-                let attr = try_stat2attr!(&v, reply);
-                reply.attr(&TTL, &attr);
+                let attr = try_stat2attr!(self, &v, reply);
+                reply.attr(&self.attr_ttl, &attr);
             }
-            Err(e) => reply.error(h2i(&e)),
+            Err(e) => reply_error!(reply, h2i(&e)),
         }
     }
 
     fn open(&mut self, req: &fuser::Request<'_>, inum: u64, flags: i32, reply: fuser::ReplyOpen) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "open", inum);
+        let _audit =
+            crate::audit::Record::new(req, "open", inum, format!("flags={flags:#x}"), || {
+                self.get_path(inum)
+            });
         log::debug!("inum {inum} flags {flags:#x}");
         let _mtx = try_mtx_lock!(MTX, reply);
+        self.try_follow();
+        try_access!(self, req, inum, crate::perm::flags2mask(flags), reply);
+        if crate::lostfound::is_virtual(inum) {
+            self.total_open += 1;
+            reply.opened(self.handles.alloc(inum), fuser::consts::FOPEN_KEEP_CACHE);
+            return;
+        }
         let Some(ip) = self.pmp.get_inode(inum) else {
-            reply.error(libc::ENOENT);
+            reply_error!(reply, libc::ENOENT);
             return;
         };
         assert_eq!(ip.get_meta().inum, inum);
+        let flags = self.cache.get_open_flags(ip.get_meta().size);
         try_inode_mut_get!(self, inum, reply); // put on release
         self.total_open += 1;
-        reply.opened(inum, fuser::consts::FOPEN_KEEP_CACHE);
+        reply.opened(self.handles.alloc(inum), flags);
     }
 
     fn readlink(&mut self, req: &fuser::Request<'_>, inum: u64, reply: fuser::ReplyData) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "readlink", inum);
+        let _audit =
+            crate::audit::Record::new(req, "readlink", inum, String::new(), || self.get_path(inum));
         log::debug!("inum {inum}");
         let _mtx = try_mtx_lock!(MTX, reply);
+        if crate::lostfound::is_virtual(inum) {
+            let ret = self
+                .get_lost_found_attr(inum)
+                .and_then(|v| Ok(self.read_lost_found(inum, v.size, 0)?));
+            match ret {
+                Ok(v) => reply.data(&v),
+                Err(e) => reply_error!(reply, h2i(&e)),
+            }
+            return;
+        }
         match self.pmp.readlinkx(inum) {
             Ok(v) => reply.data(v.as_bytes()),
-            Err(e) => reply.error(h2i(&e)),
+            Err(e) => reply_error!(reply, h2i(&e)),
         }
     }
 
@@ -205,15 +344,42 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyData,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "read", inum)
+            .offset(offset)
+            .size(size);
         log::debug!(
             "inum {inum} fh {fh} offset {offset} size {size} flags {flags:#x} \
             lock_owner {lock_owner:?}"
         );
         let _mtx = try_mtx_lock!(MTX, reply);
-        assert_eq!(inum, fh);
-        match self.pmp.preadx(inum, size.into(), try_into!(offset, reply)) {
+        self.try_follow();
+        let offset = try_into!(offset, reply);
+        if crate::lostfound::is_virtual(inum) {
+            match self.read_lost_found(inum, size.into(), offset) {
+                Ok(v) => reply.data(&v),
+                Err(e) => reply_error!(reply, e as i32),
+            }
+            return;
+        }
+        if self.is_stale_handle(fh) {
+            reply_error!(reply, libc::ESTALE);
+            return;
+        }
+        let window = self.handles.get_readahead();
+        let Some(h) = self.handles.get_mut(fh) else {
+            reply_error!(reply, libc::EBADF);
+            return;
+        };
+        assert_eq!(h.inum, inum);
+        let pmp = &mut self.pmp;
+        let dc = &mut self.datacache;
+        let mode = self.salvage;
+        match h.ra.read(offset, size.into(), window, |size, offset| {
+            crate::salvage::pread(pmp, dc, mode, inum, size, offset)
+        }) {
             Ok(v) => reply.data(&v),
-            Err(e) => reply.error(h2i(&e)),
+            Err(e) => reply_error!(reply, h2i(&e)),
         }
     }
 
@@ -226,9 +392,11 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyEmpty,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "flush", inum);
         log::debug!("inum {inum} fh {fh} lock_owner {lock_owner:?}");
         let _mtx = try_mtx_lock!(MTX, reply);
-        assert_eq!(inum, fh);
+        check_handle!(self, fh, inum, reply);
         reply.ok();
     }
 
@@ -243,15 +411,23 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyEmpty,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "release", inum);
         log::debug!(
             "inum {inum} fh {fh} flags {flags:#x} flush {flush} \
             lock_owner {lock_owner:?}"
         );
         let _mtx = try_mtx_lock!(MTX, reply);
-        assert_eq!(inum, fh);
+        if !self.put_stale_handle(fh) {
+            assert_eq!(self.handles.free(fh).map(|h| h.inum), Some(inum));
+        }
         assert!(self.total_open > 0);
         self.total_open -= 1;
-        try_inode_mut_put!(self, inum, reply);
+        self.uncache_name(inum);
+        if !crate::lostfound::is_virtual(inum) {
+            try_inode_mut_put!(self, inum, reply);
+        }
+        self.try_prune();
         reply.ok();
     }
 
@@ -263,16 +439,31 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyOpen,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "opendir", inum);
+        let _audit =
+            crate::audit::Record::new(req, "opendir", inum, format!("flags={flags:#x}"), || {
+                self.get_path(inum)
+            });
         log::debug!("inum {inum} flags {flags:#x}");
         let _mtx = try_mtx_lock!(MTX, reply);
+        self.try_follow();
+        try_access!(self, req, inum, crate::perm::flags2mask(flags), reply);
+        if inum == crate::lostfound::INUM {
+            self.total_open += 1;
+            let flags = self.cache.get_opendir_flags();
+            reply.opened(self.handles.alloc(inum), flags);
+            return;
+        }
         let Some(ip) = self.pmp.get_inode(inum) else {
-            reply.error(libc::ENOENT);
+            reply_error!(reply, libc::ENOENT);
             return;
         };
         assert_eq!(ip.get_meta().inum, inum);
         try_inode_mut_get!(self, inum, reply); // put on releasedir
         self.total_open += 1;
-        reply.opened(inum, fuser::consts::FOPEN_KEEP_CACHE);
+        let flags = self.cache.get_opendir_flags();
+        reply.opened(self.handles.alloc(inum), flags);
     }
 
     fn readdir(
@@ -284,39 +475,65 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         mut reply: fuser::ReplyDirectory,
     ) {
         debug_req!(req, self.debug > 1);
+        let dinum = self.get_h2_inum(dinum);
+        let _rec = crate::reqlog::Record::new(req, "readdir", dinum).offset(offset);
         log::debug!("dinum {dinum} fh {fh} offset {offset}");
         let _mtx = try_mtx_lock!(MTX, reply);
-        assert_eq!(dinum, fh);
-        let Some(dip) = self.pmp.get_inode(dinum) else {
-            reply.error(libc::ENOENT);
-            return;
+        self.try_follow();
+        check_handle!(self, fh, dinum, reply);
+        let ret = if dinum == crate::lostfound::INUM {
+            self.readdir_lost_found().map(|v| {
+                let names = v.iter().map(|e| e.name.clone().into()).collect();
+                (v, names)
+            })
+        } else {
+            let Some(dip) = self.pmp.get_inode(dinum) else {
+                reply_error!(reply, libc::ENOENT);
+                return;
+            };
+            if !dip.is_directory() {
+                reply_error!(reply, libc::ENOTDIR);
+                return;
+            }
+            self.readdir_salvage(dinum)
         };
-        if !dip.is_directory() {
-            reply.error(libc::ENOTDIR);
-            return;
-        }
-        match self.pmp.readdir(dinum) {
-            Ok(v) => {
+        match ret {
+            Ok((mut v, mut names)) => {
                 assert_eq!(v[0].name, ".", "{v:?}");
                 assert_eq!(v[1].name, "..", "{v:?}");
                 log::debug!("{v:?}");
+                if dinum == self.root {
+                    v[1].inum = dinum; // stay inside
+                    if self.lost_found.is_some() {
+                        v.push(libhammer2::hammer2::Dirent {
+                            inum: crate::lostfound::INUM,
+                            typ: libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY,
+                            name: crate::lostfound::NAME.to_string(),
+                        });
+                        names.push(crate::lostfound::NAME.into());
+                    }
+                }
                 if offset >= try_into!(v.len(), reply) {
                     reply.ok();
                     return;
                 }
-                for (i, e) in v[try_into!(offset, reply)..].iter().enumerate() {
+                for (e, name) in v.iter().zip(&names) {
+                    self.cache_name(dinum, name, e.inum);
+                }
+                let skip = try_into!(offset, reply);
+                for (i, (e, name)) in v.iter().zip(&names).skip(skip).enumerate() {
                     if reply.add(
-                        e.inum,
+                        self.get_fuse_inum(e.inum),
                         offset + i64::try_from(i + 1).unwrap(),
                         crate::util::obj2kind(e.typ),
-                        e.name.clone(),
+                        name,
                     ) {
                         break;
                     }
                 }
                 reply.ok();
             }
-            Err(e) => reply.error(h2i(&e)),
+            Err(e) => reply_error!(reply, h2i(&e)),
         }
     }
 
@@ -329,19 +546,29 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyEmpty,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "releasedir", inum);
         log::debug!("inum {inum} fh {fh} flags {flags:#x}");
         let _mtx = try_mtx_lock!(MTX, reply);
-        assert_eq!(inum, fh);
+        if !self.put_stale_handle(fh) {
+            assert_eq!(self.handles.free(fh).map(|h| h.inum), Some(inum));
+        }
         assert!(self.total_open > 0);
         self.total_open -= 1;
-        try_inode_mut_put!(self, inum, reply);
+        self.uncache_name(inum);
+        if !crate::lostfound::is_virtual(inum) {
+            try_inode_mut_put!(self, inum, reply);
+        }
+        self.try_prune();
         reply.ok();
     }
 
     fn statfs(&mut self, req: &fuser::Request<'_>, inum: u64, reply: fuser::ReplyStatfs) {
         debug_req!(req, self.debug > 1);
+        let _rec = crate::reqlog::Record::new(req, "statfs", inum);
         log::debug!("inum {inum}");
         let _mtx = try_mtx_lock!(MTX, reply);
+        self.try_follow();
         match self.pmp.statfs() {
             Ok(v) => reply.statfs(
                 v.f_blocks,
@@ -353,7 +580,7 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
                 v.f_namelen,
                 v.f_frsize,
             ),
-            Err(e) => reply.error(h2i(&e)),
+            Err(e) => reply_error!(reply, h2i(&e)),
         }
     }
 
@@ -361,10 +588,18 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
     // If the default_permissions mount option is given, this method is not called.
     fn access(&mut self, req: &fuser::Request<'_>, inum: u64, mask: i32, reply: fuser::ReplyEmpty) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "access", inum);
         log::debug!("inum {inum} mask {mask:#o}");
         let _mtx = try_mtx_lock!(MTX, reply);
-        reply.ok();
-        panic!("access");
+        if self.default_permissions {
+            reply.ok();
+            panic!("access");
+        }
+        match self.check_access(req, inum, mask) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply_error!(reply, h2i(&e)),
+        }
     }
 
     // Not supported on FreeBSD (see fuse_vnop_ioctl()).
@@ -380,12 +615,17 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
         reply: fuser::ReplyIoctl,
     ) {
         debug_req!(req, self.debug > 1);
+        let inum = self.get_h2_inum(inum);
+        let _rec = crate::reqlog::Record::new(req, "ioctl", inum);
+        let _audit = crate::audit::Record::new(req, "ioctl", inum, format!("cmd={cmd:#x}"), || {
+            self.get_path(inum)
+        });
         log::debug!(
             "inum {inum} fh {fh} flags {flags:#x} cmd {cmd:#x} in_data {in_data:?} \
             out_size {out_size}"
         );
         let _mtx = try_mtx_lock!(MTX, reply);
-        assert_eq!(inum, fh);
+        check_handle!(self, fh, inum, reply);
         match u64::from(cmd) {
             libhammer2::ioctl::CMD_VERSION_GET => reply.ioctl(
                 0,
@@ -394,41 +634,41 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
             libhammer2::ioctl::CMD_PFS_GET => {
                 match self.ioctl_pfs_get(libfs::cast::align_to(in_data)) {
                     Ok(v) => reply.ioctl(0, libfs::cast::as_u8_slice(&v)),
-                    Err(e) => reply.error(h2i(&e)),
+                    Err(e) => reply_error!(reply, h2i(&e)),
                 }
             }
             libhammer2::ioctl::CMD_PFS_LOOKUP => {
                 match self.ioctl_pfs_lookup(libfs::cast::align_to(in_data)) {
                     Ok(v) => reply.ioctl(0, libfs::cast::as_u8_slice(&v)),
-                    Err(e) => reply.error(h2i(&e)),
+                    Err(e) => reply_error!(reply, h2i(&e)),
                 }
             }
             libhammer2::ioctl::CMD_INODE_GET => {
                 match self.ioctl_inode_get(inum, libfs::cast::align_to(in_data)) {
                     Ok(v) => reply.ioctl(0, libfs::cast::as_u8_slice(&v)),
-                    Err(e) => reply.error(e as i32),
+                    Err(e) => reply_error!(reply, e as i32),
                 }
             }
             libhammer2::ioctl::CMD_DEBUG_DUMP => match self.ioctl_debug_dump(inum) {
                 Ok(()) => reply.ioctl(0, &[]),
-                Err(e) => reply.error(h2i(&e)),
+                Err(e) => reply_error!(reply, h2i(&e)),
             },
             libhammer2::ioctl::CMD_VOLUME_LIST => {
                 match self.ioctl_volume_list(libfs::cast::align_to(in_data)) {
                     Ok(v) => reply.ioctl(0, libfs::cast::as_u8_slice(&v)),
-                    Err(e) => reply.error(e as i32),
+                    Err(e) => reply_error!(reply, e as i32),
                 }
             }
             libhammer2::ioctl::CMD_VOLUME_LIST2 => {
                 match self.ioctl_volume_list2(libfs::cast::align_to(in_data)) {
                     Ok(v) => reply.ioctl(0, libfs::cast::as_u8_slice(&v)),
-                    Err(e) => reply.error(e as i32),
+                    Err(e) => reply_error!(reply, e as i32),
                 }
             }
             libhammer2::ioctl::CMD_CIDPRUNE => {
                 match self.ioctl_cidprune(libfs::cast::align_to(in_data)) {
                     Ok(v) => reply.ioctl(0, libfs::cast::as_u8_slice(&v)),
-                    Err(e) => reply.error(h2i(&e)),
+                    Err(e) => reply_error!(reply, h2i(&e)),
                 }
             }
             libhammer2::ioctl::CMD_PFS_CREATE
@@ -437,11 +677,22 @@ impl fuser::Filesystem for crate::Hammer2Fuse {
             | libhammer2::ioctl::CMD_INODE_SET
             | libhammer2::ioctl::CMD_BULKFREE_SCAN
             | libhammer2::ioctl::CMD_DESTROY
-            | libhammer2::ioctl::CMD_EMERG_MODE
-            | libhammer2::ioctl::CMD_GROWFS => reply.error(libc::EOPNOTSUPP),
+            | libhammer2::ioctl::CMD_GROWFS => reply_error!(reply, libc::EOPNOTSUPP),
+            libhammer2::ioctl::CMD_EMERG_MODE => match self.ioctl_emerg_mode(req.uid(), in_data) {
+                Ok(v) => reply.ioctl(0, &v.to_ne_bytes()),
+                Err(e) => reply_error!(reply, e as i32),
+            },
+            crate::ioctl::FS_IOC_GETFLAGS | crate::ioctl::FS_IOC32_GETFLAGS
+                if libfs::os::is_linux() =>
+            {
+                match self.ioctl_getflags(inum, out_size) {
+                    Ok(v) => reply.ioctl(0, &v),
+                    Err(e) => reply_error!(reply, e as i32),
+                }
+            }
             _ => {
                 log::error!("invalid ioctl command {cmd:#x}");
-                reply.error(libc::EINVAL);
+                reply_error!(reply, libc::EINVAL);
             }
         }
     }
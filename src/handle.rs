@@ -0,0 +1,101 @@
+// file handles returned by open / opendir
+#[derive(Debug)]
+pub(crate) struct Handle {
+    pub(crate) inum: u64,
+    pub(crate) ra: crate::readahead::ReadAhead,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Handles {
+    map: std::collections::HashMap<u64, Handle>,
+    next: u64,
+    readahead: u64, // max window
+}
+
+impl Handles {
+    pub(crate) fn new(opt: &crate::option::Opt) -> Self {
+        Self {
+            map: std::collections::HashMap::new(),
+            next: 0,
+            readahead: opt.readahead.unwrap_or(crate::readahead::DEFAULT_WINDOW),
+        }
+    }
+
+    pub(crate) fn alloc(&mut self, inum: u64) -> u64 {
+        self.next += 1;
+        let fh = self.next;
+        self.map.insert(
+            fh,
+            Handle {
+                inum,
+                ra: crate::readahead::ReadAhead::new(),
+            },
+        );
+        fh
+    }
+
+    pub(crate) fn free(&mut self, fh: u64) -> Option<Handle> {
+        self.map.remove(&fh)
+    }
+
+    pub(crate) fn get_inum(&self, fh: u64) -> Option<u64> {
+        self.map.get(&fh).map(|h| h.inum)
+    }
+
+    pub(crate) fn get_inums(&self) -> impl Iterator<Item = u64> + '_ {
+        self.map.values().map(|h| h.inum)
+    }
+
+    pub(crate) fn has_inum(&self, inum: u64) -> bool {
+        self.map.values().any(|h| h.inum == inum)
+    }
+
+    pub(crate) fn get_mut(&mut self, fh: u64) -> Option<&mut Handle> {
+        self.map.get_mut(&fh)
+    }
+
+    pub(crate) fn get_readahead(&self) -> u64 {
+        self.readahead
+    }
+
+    pub(crate) fn clear_readahead(&mut self) {
+        for h in self.map.values_mut() {
+            h.ra.clear();
+        }
+    }
+
+    // drops handles of inodes in l, returns dropped handles
+    pub(crate) fn remove_inodes(&mut self, l: &[u64]) -> Vec<u64> {
+        let v: Vec<_> = self
+            .map
+            .iter()
+            .filter(|(_, h)| l.contains(&h.inum))
+            .map(|(&fh, _)| fh)
+            .collect();
+        for fh in &v {
+            self.map.remove(fh);
+        }
+        v
+    }
+}
+
+impl crate::Hammer2Fuse {
+    // inodes referenced by kernel lookup and open files
+    pub(crate) fn get_ref_inodes(&self) -> Vec<u64> {
+        let mut l: Vec<_> = self.nlookup.keys().copied().collect();
+        l.extend(self.handles.get_inums());
+        l.sort_unstable();
+        l.dedup();
+        l
+    }
+}
+
+// take references on pmp for inodes from get_ref_inodes
+pub(crate) fn get_refs(pmp: &mut libhammer2::hammer2::Hammer2, l: &[u64]) -> nix::Result<()> {
+    for &inum in l {
+        if let Some(ip) = pmp.get_inode_mut(inum) {
+            ip.get()?;
+        }
+    }
+    Ok(())
+}
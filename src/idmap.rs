@@ -0,0 +1,155 @@
+// uid / gid remapping applied to inode attributes
+//
+// squash          all ids to the mounting user / group
+// from/to[/count] ids in [from, from+count) to [to, to+count), separated by ":"
+// /path           map file with "from to [count]" per line
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IdMap {
+    squash: Option<u32>,
+    ranges: Vec<(u32, u32, u32)>,
+}
+
+impl IdMap {
+    pub(crate) fn new(s: &str, self_id: u32) -> nix::Result<Self> {
+        if s == "squash" {
+            return Ok(Self {
+                squash: Some(self_id),
+                ..Default::default()
+            });
+        }
+        let mut ranges = vec![];
+        if s.starts_with('/') {
+            let buf = match std::fs::read_to_string(s) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("{s}: {e}");
+                    return Err(nix::errno::Errno::ENOENT);
+                }
+            };
+            for l in buf.lines() {
+                let l = l.split('#').next().unwrap_or_default();
+                let v: Vec<&str> = l.split_whitespace().collect();
+                if !v.is_empty() {
+                    ranges.push(parse_range(&v)?);
+                }
+            }
+        } else {
+            for x in s.split(':') {
+                ranges.push(parse_range(&x.split('/').collect::<Vec<&str>>())?);
+            }
+        }
+        Ok(Self {
+            squash: None,
+            ranges,
+        })
+    }
+
+    pub(crate) fn map(&self, id: u32) -> u32 {
+        if let Some(v) = self.squash {
+            return v;
+        }
+        for &(from, to, count) in &self.ranges {
+            if id >= from && id - from < count {
+                return to + (id - from);
+            }
+        }
+        id
+    }
+}
+
+// uid and gid maps of a mount
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IdMaps {
+    uid: IdMap,
+    gid: IdMap,
+}
+
+impl IdMaps {
+    pub(crate) fn new(opt: &crate::option::Opt) -> Self {
+        Self {
+            uid: opt.uidmap.clone(),
+            gid: opt.gidmap.clone(),
+        }
+    }
+
+    pub(crate) fn map_attr(&self, attr: &mut fuser::FileAttr) {
+        attr.uid = self.uid.map(attr.uid);
+        attr.gid = self.gid.map(attr.gid);
+    }
+}
+
+fn parse_range(v: &[&str]) -> nix::Result<(u32, u32, u32)> {
+    let f = |x: &str| {
+        x.parse::<u32>().map_err(|_| {
+            eprintln!("invalid id {x}");
+            nix::errno::Errno::EINVAL
+        })
+    };
+    let (from, to, count) = match v.len() {
+        2 => (f(v[0])?, f(v[1])?, 1),
+        3 => (f(v[0])?, f(v[1])?, f(v[2])?),
+        _ => {
+            eprintln!("invalid id map {}", v.join(" "));
+            return Err(nix::errno::Errno::EINVAL);
+        }
+    };
+    if count == 0 || from.checked_add(count - 1).is_none() || to.checked_add(count - 1).is_none() {
+        eprintln!("invalid id range {from} {to} {count}");
+        return Err(nix::errno::Errno::EINVAL);
+    }
+    Ok((from, to, count))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_squash() {
+        let m = super::IdMap::new("squash", 1000).unwrap();
+        assert_eq!(m.map(0), 1000);
+        assert_eq!(m.map(1001), 1000);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let m = super::IdMap::new("0/1000:100/2000/10", 0).unwrap();
+        assert_eq!(m.map(0), 1000);
+        assert_eq!(m.map(1), 1);
+        assert_eq!(m.map(99), 99);
+        assert_eq!(m.map(100), 2000);
+        assert_eq!(m.map(109), 2009);
+        assert_eq!(m.map(110), 110);
+        assert_eq!(m.map(u32::MAX), u32::MAX);
+
+        let m = super::IdMap::default();
+        assert_eq!(m.map(123), 123);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for s in [
+            "",
+            "0",
+            "a/1",
+            "0/1/0",
+            "0/1/2/3",
+            "4294967295/0/2",
+            "0/4294967295/2",
+        ] {
+            assert!(super::IdMap::new(s, 0).is_err(), "{s}");
+        }
+        assert!(super::IdMap::new("4294967295/0/1", 0).is_ok());
+    }
+
+    #[test]
+    fn test_file() {
+        let f = std::env::temp_dir().join(format!("hammer2-fuse-idmap.{}", std::process::id()));
+        std::fs::write(&f, "# comment\n0 1000\n\n500 600 2 # trailing\n").unwrap();
+        let m = super::IdMap::new(&f.to_string_lossy(), 0);
+        std::fs::remove_file(&f).unwrap();
+        let m = m.unwrap();
+        assert_eq!(m.map(0), 1000);
+        assert_eq!(m.map(501), 601);
+        assert_eq!(m.map(502), 502);
+        assert!(super::IdMap::new("/nonexistent/idmap", 0).is_err());
+    }
+}
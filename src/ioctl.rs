@@ -1,5 +1,9 @@
 use libhammer2::ErrorExt;
 
+// _IOR('f', 1, long) and _IOR('f', 1, int), used by lsattr(1)
+pub(crate) const FS_IOC_GETFLAGS: u64 = 0x8008_6601;
+pub(crate) const FS_IOC32_GETFLAGS: u64 = 0x8004_6601;
+
 impl crate::Hammer2Fuse {
     pub(crate) fn ioctl_version_get(
         &self,
@@ -14,57 +18,7 @@ impl crate::Hammer2Fuse {
         &mut self,
         ioc: &libhammer2::ioctl::IocPfs,
     ) -> libhammer2::Result<libhammer2::ioctl::IocPfs> {
-        let (mut pcid, mut cid) = if ioc.name_key == u64::MAX {
-            let cid = self.pmp.get_inode_chain(
-                libhammer2::inode::INUM_PFS_ROOT,
-                libhammer2::hammer2::RESOLVE_ALWAYS,
-            )?;
-            (libhammer2::chain::CID_NONE, cid)
-        } else {
-            let pcid = self.pmp.get_inode_chain(
-                libhammer2::inode::INUM_SUP_ROOT,
-                libhammer2::hammer2::RESOLVE_ALWAYS,
-            )?;
-            if pcid == libhammer2::chain::CID_NONE {
-                return Err(nix::errno::Errno::EIO.into());
-            }
-            let (pcid, cid, _) =
-                self.pmp
-                    .lookup_chain(pcid, ioc.name_key, libhammer2::fs::HAMMER2_KEY_MAX, 0)?;
-            (pcid, cid)
-        };
-        while cid != libhammer2::chain::CID_NONE {
-            if self.get_chain(cid)?.get_blockref().typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE {
-                break;
-            }
-            (pcid, cid, _) =
-                self.pmp
-                    .get_next_chain(pcid, cid, libhammer2::fs::HAMMER2_KEY_MAX, 0)?;
-        }
-        if cid == libhammer2::chain::CID_NONE {
-            return Err(nix::errno::Errno::ENOENT.into());
-        }
-        let ipdata = self.get_chain(cid)?.as_inode_data();
-        let mut ioc = *ioc;
-        ioc.name_key = ipdata.meta.name_key;
-        ioc.pfs_type = ipdata.meta.pfs_type;
-        ioc.pfs_subtype = ipdata.meta.pfs_subtype;
-        ioc.pfs_clid = ipdata.meta.pfs_clid;
-        ioc.pfs_fsid = ipdata.meta.pfs_fsid;
-        ioc.copy_name(&ipdata.filename);
-        if pcid == libhammer2::chain::CID_NONE {
-            ioc.name_next = u64::MAX;
-        } else {
-            (_, cid, _) = self
-                .pmp
-                .get_next_chain(pcid, cid, libhammer2::fs::HAMMER2_KEY_MAX, 0)?;
-            ioc.name_next = if cid == libhammer2::chain::CID_NONE {
-                u64::MAX
-            } else {
-                self.get_chain(cid)?.get_blockref().key
-            };
-        }
-        Ok(ioc)
+        pfs_get(&mut self.pmp, ioc)
     }
 
     pub(crate) fn ioctl_pfs_lookup(
@@ -135,7 +89,9 @@ impl crate::Hammer2Fuse {
         }
     }
 
-    #[allow(clippy::unused_self)]
+    // Entries are copied out to user pointer ioc.volumes, which requires
+    // FUSE_IOCTL_RETRY not supported by fuser. Only a query for the number
+    // of volumes with null pointer is supported, use CMD_VOLUME_LIST2.
     pub(crate) fn ioctl_volume_list(
         &self,
         ioc: &libhammer2::ioctl::IocVolumeList,
@@ -143,7 +99,13 @@ impl crate::Hammer2Fuse {
         if ioc.nvolumes > libhammer2::fs::HAMMER2_MAX_VOLUMES.into() {
             return Err(nix::errno::Errno::EINVAL);
         }
-        Err(nix::errno::Errno::EOPNOTSUPP)
+        if ioc.volumes != 0 {
+            return Err(nix::errno::Errno::EOPNOTSUPP);
+        }
+        let mut ioc = *ioc;
+        ioc.nvolumes = self.pmp.get_volumes().len().try_into().or_nix_range()?;
+        ioc.version = self.pmp.get_volume_data().version;
+        Ok(ioc)
     }
 
     pub(crate) fn ioctl_volume_list2(
@@ -172,6 +134,27 @@ impl crate::Hammer2Fuse {
         Ok(ioc)
     }
 
+    // nonzero enables salvage mode, keeping "zero" mode if already set
+    // root or user running the daemon only
+    pub(crate) fn ioctl_emerg_mode(&mut self, uid: u32, in_data: &[u8]) -> nix::Result<i32> {
+        if uid != 0 && uid != nix::unistd::getuid().as_raw() {
+            return Err(nix::errno::Errno::EPERM);
+        }
+        let v = i32::from_ne_bytes(
+            in_data
+                .get(..4)
+                .and_then(|x| x.try_into().ok())
+                .ok_or(nix::errno::Errno::EINVAL)?,
+        );
+        if v == 0 {
+            self.salvage = crate::salvage::SalvageMode::Off;
+        } else if self.salvage == crate::salvage::SalvageMode::Off {
+            self.salvage = crate::salvage::SalvageMode::Skip;
+        }
+        log::info!("salvage mode {:?}", self.salvage);
+        Ok(v)
+    }
+
     pub(crate) fn ioctl_cidprune(
         &mut self,
         ioc: &libhammer2::ioctl::IocCidPrune,
@@ -185,6 +168,7 @@ impl crate::Hammer2Fuse {
         match self.pmp.prune_chain() {
             Ok(t) => {
                 // file data may be cached by FOPEN_KEEP_CACHE if zero
+                self.invalidate_inodes();
                 let mut ioc = *ioc;
                 ioc.vchain_total = t.0.try_into().or_nix_range()?;
                 ioc.fchain_total = t.1.try_into().or_nix_range()?;
@@ -194,7 +178,83 @@ impl crate::Hammer2Fuse {
         }
     }
 
-    fn get_chain(&self, cid: libhammer2::chain::Cid) -> nix::Result<&libhammer2::chain::Chain> {
-        self.pmp.get_chain(cid).ok_or(nix::errno::Errno::ENOENT)
+    pub(crate) fn ioctl_getflags(&self, inum: u64, out_size: u32) -> nix::Result<Vec<u8>> {
+        let Some(ip) = self.pmp.get_inode(inum) else {
+            return Err(nix::errno::Errno::ENOENT);
+        };
+        let flags = crate::util::uflags2linux(ip.get_meta().uflags);
+        let mut v = flags.to_ne_bytes().to_vec();
+        if out_size < v.len().try_into().or_nix_range()? {
+            return Err(nix::errno::Errno::EINVAL);
+        }
+        v.resize(out_size.try_into().or_nix_range()?, 0);
+        Ok(v)
+    }
+
+    pub(crate) fn get_chain(
+        &self,
+        cid: libhammer2::chain::Cid,
+    ) -> nix::Result<&libhammer2::chain::Chain> {
+        get_chain(&self.pmp, cid)
+    }
+}
+
+fn get_chain(
+    pmp: &libhammer2::hammer2::Hammer2,
+    cid: libhammer2::chain::Cid,
+) -> nix::Result<&libhammer2::chain::Chain> {
+    pmp.get_chain(cid).ok_or(nix::errno::Errno::ENOENT)
+}
+
+// CMD_PFS_GET walks super-root by name key
+pub(crate) fn pfs_get(
+    pmp: &mut libhammer2::hammer2::Hammer2,
+    ioc: &libhammer2::ioctl::IocPfs,
+) -> libhammer2::Result<libhammer2::ioctl::IocPfs> {
+    let (mut pcid, mut cid) = if ioc.name_key == u64::MAX {
+        let cid = pmp.get_inode_chain(
+            libhammer2::inode::INUM_PFS_ROOT,
+            libhammer2::hammer2::RESOLVE_ALWAYS,
+        )?;
+        (libhammer2::chain::CID_NONE, cid)
+    } else {
+        let pcid = pmp.get_inode_chain(
+            libhammer2::inode::INUM_SUP_ROOT,
+            libhammer2::hammer2::RESOLVE_ALWAYS,
+        )?;
+        if pcid == libhammer2::chain::CID_NONE {
+            return Err(nix::errno::Errno::EIO.into());
+        }
+        let (pcid, cid, _) =
+            pmp.lookup_chain(pcid, ioc.name_key, libhammer2::fs::HAMMER2_KEY_MAX, 0)?;
+        (pcid, cid)
+    };
+    while cid != libhammer2::chain::CID_NONE {
+        if get_chain(pmp, cid)?.get_blockref().typ == libhammer2::fs::HAMMER2_BREF_TYPE_INODE {
+            break;
+        }
+        (pcid, cid, _) = pmp.get_next_chain(pcid, cid, libhammer2::fs::HAMMER2_KEY_MAX, 0)?;
+    }
+    if cid == libhammer2::chain::CID_NONE {
+        return Err(nix::errno::Errno::ENOENT.into());
+    }
+    let ipdata = get_chain(pmp, cid)?.as_inode_data();
+    let mut ioc = *ioc;
+    ioc.name_key = ipdata.meta.name_key;
+    ioc.pfs_type = ipdata.meta.pfs_type;
+    ioc.pfs_subtype = ipdata.meta.pfs_subtype;
+    ioc.pfs_clid = ipdata.meta.pfs_clid;
+    ioc.pfs_fsid = ipdata.meta.pfs_fsid;
+    ioc.copy_name(&ipdata.filename);
+    if pcid == libhammer2::chain::CID_NONE {
+        ioc.name_next = u64::MAX;
+    } else {
+        (_, cid, _) = pmp.get_next_chain(pcid, cid, libhammer2::fs::HAMMER2_KEY_MAX, 0)?;
+        ioc.name_next = if cid == libhammer2::chain::CID_NONE {
+            u64::MAX
+        } else {
+            get_chain(pmp, cid)?.get_blockref().key
+        };
     }
+    Ok(ioc)
 }
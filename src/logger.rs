@@ -0,0 +1,169 @@
+use std::io::Write;
+
+static REOPEN: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+const LEVELS: [log::LevelFilter; 6] = [
+    log::LevelFilter::Off,
+    log::LevelFilter::Error,
+    log::LevelFilter::Warn,
+    log::LevelFilter::Info,
+    log::LevelFilter::Debug,
+    log::LevelFilter::Trace,
+];
+
+pub(crate) const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+pub(crate) const DEFAULT_ROTATE: usize = 4;
+
+// log file opened in append mode and rotated by size, reopened on SIGHUP
+pub(crate) struct FileLogger {
+    path: String,
+    max_size: u64,
+    rotate: usize,
+    json: bool,
+    file: std::sync::Mutex<std::fs::File>,
+}
+
+impl FileLogger {
+    pub(crate) fn new(
+        path: &str,
+        max_size: u64,
+        rotate: usize,
+        json: bool,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.to_string(),
+            max_size,
+            rotate,
+            json,
+            file: std::sync::Mutex::new(open_file(path)?),
+        })
+    }
+
+    fn format(&self, record: &log::Record<'_>) -> String {
+        let time = crate::util::get_time_string();
+        if !self.json {
+            format!("{time} [{}] {}", record.level(), record.args())
+        } else if record.target() == crate::reqlog::TARGET {
+            // already in JSON
+            format!(
+                "{{\"time\":\"{time}\",\"level\":\"{}\",\"request\":{}}}",
+                record.level(),
+                record.args()
+            )
+        } else {
+            serde_json::json!({
+                "time": time,
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "msg": record.args().to_string(),
+            })
+            .to_string()
+        }
+    }
+
+    // truncated in place if no rotated files are kept
+    fn rotate_file(&self, file: &mut std::fs::File) -> std::io::Result<()> {
+        if self.rotate == 0 {
+            return file.set_len(0);
+        }
+        for i in (1..self.rotate).rev() {
+            let f = format!("{}.{i}", self.path);
+            if std::path::Path::new(&f).exists() {
+                std::fs::rename(&f, format!("{}.{}", self.path, i + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, format!("{}.1", self.path))?;
+        *file = open_file(&self.path)?;
+        Ok(())
+    }
+}
+
+impl log::Log for FileLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let Ok(mut f) = self.file.lock() else {
+            return;
+        };
+        if REOPEN.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            match open_file(&self.path) {
+                Ok(v) => *f = v,
+                Err(e) => eprintln!("{}: {e}", self.path),
+            }
+        }
+        if writeln!(f, "{}", self.format(record)).is_err() {
+            return;
+        }
+        if self.max_size > 0
+            && let Ok(v) = f.metadata()
+            && v.len() >= self.max_size
+            && let Err(e) = self.rotate_file(&mut f)
+        {
+            eprintln!("{}: {e}", self.path);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut f) = self.file.lock() {
+            let _ = f.flush();
+        }
+    }
+}
+
+fn open_file(path: &str) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+pub(crate) fn get_default_level() -> log::LevelFilter {
+    if libfs::is_debug_set() {
+        log::LevelFilter::Trace
+    } else {
+        log::LevelFilter::Info
+    }
+}
+
+// SIGHUP reopens log file (e.g. after logrotate),
+// SIGUSR1 / SIGUSR2 raise / lower log level.
+// Only installed with FileLogger, default actions apply otherwise.
+extern "C" fn handle_signal(sig: libc::c_int) {
+    match sig {
+        libc::SIGHUP => REOPEN.store(true, std::sync::atomic::Ordering::Relaxed),
+        libc::SIGUSR1 | libc::SIGUSR2 => {
+            let i = LEVELS
+                .iter()
+                .position(|&x| x == log::max_level())
+                .unwrap_or(0);
+            let i = if sig == libc::SIGUSR1 {
+                std::cmp::min(i + 1, LEVELS.len() - 1)
+            } else {
+                i.saturating_sub(1)
+            };
+            log::set_max_level(LEVELS[i]);
+        }
+        _ => (),
+    }
+}
+
+pub(crate) fn init_signal() -> nix::Result<()> {
+    let sa = nix::sys::signal::SigAction::new(
+        nix::sys::signal::SigHandler::Handler(handle_signal),
+        nix::sys::signal::SaFlags::SA_RESTART,
+        nix::sys::signal::SigSet::empty(),
+    );
+    for sig in [
+        nix::sys::signal::Signal::SIGHUP,
+        nix::sys::signal::Signal::SIGUSR1,
+        nix::sys::signal::Signal::SIGUSR2,
+    ] {
+        unsafe { nix::sys::signal::sigaction(sig, &sa) }?;
+    }
+    Ok(())
+}
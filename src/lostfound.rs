@@ -0,0 +1,725 @@
+use libhammer2::ErrorExt;
+
+// deleted inodes found by raw scan of the volume, which survive
+// until bulkfree reuses the blocks
+pub(crate) const NAME: &str = ".lost+found";
+pub(crate) const INUM: u64 = 1 << 63; // virtual directory, entries follow
+
+const INODE_BYTES: usize = std::mem::size_of::<libhammer2::fs::Hammer2InodeData>();
+const SCAN_BYTES: usize = 1024 * 1024;
+const MAX_DEPTH: usize = 16;
+const MAX_BLOCKS: usize = 1 << 20; // indirect and data blocks per file
+
+type Blockref = libhammer2::fs::Hammer2Blockref;
+
+pub(crate) fn is_virtual(inum: u64) -> bool {
+    inum >= INUM
+}
+
+// None if out of range, blocks may have been reused
+fn get_psize(bref: &Blockref) -> Option<usize> {
+    let radix = bref.data_off & libhammer2::fs::HAMMER2_OFF_MASK_RADIX;
+    if (libhammer2::fs::HAMMER2_MIN_RADIX..=libhammer2::fs::HAMMER2_MAX_RADIX).contains(&radix) {
+        usize::try_from(1_u64 << radix).ok()
+    } else {
+        None
+    }
+}
+
+// logical size of data block
+fn get_lsize(bref: &Blockref) -> Option<usize> {
+    let v = 1_u64.checked_shl(bref.keybits.into())?;
+    if v <= libhammer2::fs::HAMMER2_PBUFSIZE {
+        usize::try_from(v).ok()
+    } else {
+        None
+    }
+}
+
+fn get_end(bref: &Blockref) -> Option<u64> {
+    bref.key.checked_add(get_lsize(bref)?.try_into().ok()?)
+}
+
+fn get_blockrefs(b: &[u8]) -> Vec<Blockref> {
+    b.chunks_exact(libhammer2::fs::HAMMER2_BLOCKREF_BYTES)
+        .map(|x| *libfs::cast::align_to::<Blockref>(x))
+        .collect()
+}
+
+#[derive(Debug)]
+pub(crate) struct Inode {
+    pub(crate) inum: u64,
+    pub(crate) typ: u8,
+    pub(crate) mode: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u64,
+    pub(crate) ctime: u64,
+    pub(crate) mtime: u64,
+    pub(crate) atime: u64,
+    pub(crate) btime: u64,
+    pub(crate) iparent: u64,
+    pub(crate) name: String,
+    pub(crate) offset: u64, // of inode on volume
+    uflags: u32,
+    data: Option<Vec<u8>>, // embedded
+    blockset: Vec<Blockref>,
+    leaves: Option<Vec<Blockref>>, // sorted data blockrefs
+}
+
+// Some if ipdata looks like an inode, name_key must match name
+fn parse_inode(ipdata: &libhammer2::fs::Hammer2InodeData, offset: u64) -> Option<Inode> {
+    let meta = &ipdata.meta;
+    if meta.version != libhammer2::fs::HAMMER2_INODE_VERSION_ONE
+        || meta.pfs_type != libhammer2::fs::HAMMER2_PFSTYPE_NONE
+    {
+        return None;
+    }
+    if !matches!(
+        meta.typ,
+        libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY
+            | libhammer2::fs::HAMMER2_OBJTYPE_REGFILE
+            | libhammer2::fs::HAMMER2_OBJTYPE_FIFO
+            | libhammer2::fs::HAMMER2_OBJTYPE_CDEV
+            | libhammer2::fs::HAMMER2_OBJTYPE_BDEV
+            | libhammer2::fs::HAMMER2_OBJTYPE_SOFTLINK
+            | libhammer2::fs::HAMMER2_OBJTYPE_SOCKET
+    ) {
+        return None;
+    }
+    let n = usize::from(meta.name_len);
+    if meta.inum <= libhammer2::inode::INUM_PFS_ROOT || n == 0 {
+        return None;
+    }
+    let name = ipdata.filename.get(..n)?;
+    if name.iter().any(|&x| x == b'/' || x == 0) || libhammer2::subs::dirhash(name) != meta.name_key
+    {
+        return None;
+    }
+    let (data, blockset) = if meta.op_flags & libhammer2::fs::HAMMER2_OPFLAG_DIRECTDATA != 0 {
+        let n = usize::try_from(meta.size).ok()?;
+        (Some(ipdata.u.get(..n)?.to_vec()), vec![])
+    } else {
+        (None, get_blockrefs(&ipdata.u))
+    };
+    Some(Inode {
+        inum: meta.inum,
+        typ: meta.typ,
+        mode: meta.mode & 0o7777,
+        uid: libhammer2::subs::hammer2_to_unix_xid(&meta.uid),
+        gid: libhammer2::subs::hammer2_to_unix_xid(&meta.gid),
+        size: meta.size,
+        ctime: meta.ctime,
+        mtime: meta.mtime,
+        atime: meta.atime,
+        btime: meta.btime,
+        iparent: meta.iparent,
+        name: String::from_utf8_lossy(name).into_owned(),
+        offset,
+        uflags: meta.uflags,
+        data,
+        blockset,
+        leaves: None,
+    })
+}
+
+// latest copy of each inode found on volume, 1KiB aligned,
+// keyed by btime too since PFS have separate inum spaces
+fn scan(src: &mut dyn crate::blksrc::BlockSource) -> std::io::Result<Vec<Inode>> {
+    let mut m = std::collections::BTreeMap::<(u64, u64), Inode>::new();
+    let mut b = vec![0; SCAN_BYTES];
+    let mut offset = 0;
+    while offset < src.get_size() {
+        if offset % libhammer2::fs::HAMMER2_ZONE_BYTES64 == 0 {
+            log::info!("scanning {offset:#x}/{:#x}", src.get_size());
+        }
+        let n = src.read_at(&mut b, offset)?;
+        if n == 0 {
+            break;
+        }
+        for (i, x) in b[..n].chunks_exact(INODE_BYTES).enumerate() {
+            let Some(ip) = u64::try_from(i * INODE_BYTES)
+                .ok()
+                .and_then(|x| offset.checked_add(x))
+                .and_then(|offset| parse_inode(libfs::cast::align_to(x), offset))
+            else {
+                continue;
+            };
+            match m.get(&(ip.inum, ip.btime)) {
+                Some(v) if (v.ctime, v.mtime) >= (ip.ctime, ip.mtime) => (),
+                _ => {
+                    m.insert((ip.inum, ip.btime), ip);
+                }
+            }
+        }
+        offset += u64::try_from(n).map_err(|_| invalid_data())?;
+    }
+    Ok(m.into_values().collect())
+}
+
+// same inode still live in pmp, inum alone may belong to another PFS
+fn is_reachable(pmp: &mut libhammer2::hammer2::Hammer2, ip: &Inode) -> bool {
+    let Ok(cid) = pmp.get_inode_chain(ip.inum, libhammer2::hammer2::RESOLVE_ALWAYS) else {
+        return false;
+    };
+    let Some(chain) = pmp.get_chain(cid) else {
+        return false;
+    };
+    let ipdata = chain.as_inode_data();
+    let n = usize::from(ipdata.meta.name_len);
+    ipdata.meta.inum == ip.inum
+        && ipdata.meta.btime == ip.btime
+        && ipdata
+            .filename
+            .get(..n)
+            .is_some_and(|x| String::from_utf8_lossy(x) == ip.name)
+}
+
+// drop inodes still live in any PFS
+fn filter_reachable(path: &str, l: &mut Vec<Inode>) -> crate::Result<()> {
+    let mut pmp = libhammer2::mount(path, &[])?;
+    let v = crate::pfs::get_pfs_list(&mut pmp);
+    pmp.unmount()?;
+    for x in v? {
+        let name = String::from_utf8_lossy(x.get_name()?).into_owned();
+        let mut pmp = libhammer2::mount(&format!("{path}@{name}"), &[])?;
+        l.retain(|ip| !is_reachable(&mut pmp, ip));
+        pmp.unmount()?;
+    }
+    Ok(())
+}
+
+fn find(
+    special: &str,
+    selector: Option<&str>,
+    path: &str,
+) -> crate::Result<(Box<dyn crate::blksrc::BlockSource>, Vec<Inode>)> {
+    let mut src = crate::blksrc::open_raw(special, selector)?;
+    // blockref data_off spans all volumes
+    let (hdrs, best) = crate::probe::read_headers(&mut *src)?;
+    match best.and_then(|i| hdrs[i].as_ref()) {
+        Some(h) if h.nvolumes <= 1 => (),
+        Some(_) => return Err(Box::new(nix::errno::Errno::EOPNOTSUPP)),
+        None => return Err(Box::new(nix::errno::Errno::EINVAL)),
+    }
+    let mut l = scan(&mut *src)?;
+    let n = l.len();
+    filter_reachable(path, &mut l)?;
+    log::info!("{} of {n} inodes unreachable", l.len());
+    Ok((src, l))
+}
+
+fn invalid_data() -> std::io::Error {
+    std::io::Error::from(std::io::ErrorKind::InvalidData)
+}
+
+// indirect blocks are not compressed and lsize is psize
+fn read_block(
+    src: &mut dyn crate::blksrc::BlockSource,
+    bref: &Blockref,
+) -> std::io::Result<Vec<u8>> {
+    let comp = libhammer2::fs::dec_comp(bref.methods);
+    let lsize = match bref.typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_DATA => get_lsize(bref).ok_or_else(invalid_data)?,
+        _ if comp == libhammer2::fs::HAMMER2_COMP_NONE => 0,
+        _ => return Err(invalid_data()),
+    };
+    if comp == libhammer2::fs::HAMMER2_COMP_AUTOZERO {
+        return Ok(vec![0; lsize]);
+    }
+    let psize = get_psize(bref).ok_or_else(invalid_data)?;
+    let mut b = vec![0; psize];
+    if src.read_at(&mut b, bref.data_off & libhammer2::fs::HAMMER2_OFF_MASK)? != psize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+    }
+    if comp == libhammer2::fs::HAMMER2_COMP_NONE {
+        return Ok(b);
+    }
+    libhammer2::subs::decompress(comp, &b, lsize).map_err(|_| invalid_data())
+}
+
+// data blockrefs under blockset within file size, unreadable or
+// already visited indirect blocks are skipped
+fn get_leaves(
+    src: &mut dyn crate::blksrc::BlockSource,
+    brefs: &[Blockref],
+    size: u64,
+    depth: usize,
+    visited: &mut std::collections::HashSet<u64>,
+    leaves: &mut Vec<Blockref>,
+) {
+    for bref in brefs {
+        if visited.len() + leaves.len() >= MAX_BLOCKS {
+            log::error!("too many blocks");
+            return;
+        }
+        match bref.typ {
+            libhammer2::fs::HAMMER2_BREF_TYPE_DATA
+                if bref.key < size && get_end(bref).is_some() =>
+            {
+                leaves.push(*bref);
+            }
+            libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT
+                if depth < MAX_DEPTH && visited.insert(bref.data_off) =>
+            {
+                match read_block(src, bref) {
+                    Ok(b) => get_leaves(src, &get_blockrefs(&b), size, depth + 1, visited, leaves),
+                    Err(e) => log::error!("indirect block {:#x}: {e}", bref.data_off),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// name, then name.inum, then name.inum.offset on collision
+fn get_names(inodes: &[Inode]) -> std::collections::HashMap<String, usize> {
+    let mut names = std::collections::HashMap::new();
+    for (i, ip) in inodes.iter().enumerate() {
+        let l = [
+            ip.name.clone(),
+            format!("{}.{}", ip.name, ip.inum),
+            format!("{}.{}.{:x}", ip.name, ip.inum, ip.offset),
+        ];
+        if let Some(name) = l.into_iter().find(|x| !names.contains_key(x)) {
+            names.insert(name, i);
+        }
+    }
+    names
+}
+
+// unreachable regular files and symlinks
+struct Scan {
+    src: Box<dyn crate::blksrc::BlockSource>,
+    inodes: Vec<Inode>,
+    names: std::collections::HashMap<String, usize>,
+}
+
+impl Scan {
+    fn new(special: &str, selector: Option<&str>, path: &str) -> crate::Result<Self> {
+        let (src, mut inodes) = find(special, selector, path)?;
+        inodes.retain(|ip| {
+            matches!(
+                ip.typ,
+                libhammer2::fs::HAMMER2_OBJTYPE_REGFILE | libhammer2::fs::HAMMER2_OBJTYPE_SOFTLINK
+            )
+        });
+        let names = get_names(&inodes);
+        Ok(Self { src, inodes, names })
+    }
+}
+
+pub(crate) struct LostFound {
+    special: String,
+    selector: Option<String>,
+    path: String,
+    scan: Option<nix::Result<Scan>>, // on first lookup or readdir
+    time: std::time::SystemTime,
+}
+
+impl std::fmt::Debug for LostFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LostFound")
+            .field("special", &self.special)
+            .field(
+                "inodes",
+                &self
+                    .scan
+                    .as_ref()
+                    .map(|v| v.as_ref().map(|x| x.inodes.len())),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl LostFound {
+    // path is passed to libhammer2 to walk PFS, volume is scanned
+    // on first use as it takes as long as reading the whole device
+    pub(crate) fn new(special: &str, selector: Option<&str>, path: &str) -> Self {
+        Self {
+            special: special.to_string(),
+            selector: selector.map(str::to_string),
+            path: path.to_string(),
+            scan: None,
+            time: std::time::SystemTime::now(),
+        }
+    }
+
+    // failure is kept, no rescan per request
+    fn get_scan(&mut self) -> nix::Result<&mut Scan> {
+        let scan = self.scan.get_or_insert_with(|| {
+            log::info!("{}: scanning for {NAME}", self.special);
+            Scan::new(&self.special, self.selector.as_deref(), &self.path).map_err(|e| {
+                log::error!("{}: {e}", self.special);
+                nix::errno::Errno::EIO
+            })
+        });
+        scan.as_mut().map_err(|e| *e)
+    }
+
+    // entries exist only once scanned
+    fn get_scanned(&mut self) -> Option<&mut Scan> {
+        self.scan.as_mut().and_then(|v| v.as_mut().ok())
+    }
+
+    fn get_inode(&mut self, inum: u64) -> Option<&Inode> {
+        self.get_scanned()?
+            .inodes
+            .get(usize::try_from(inum.checked_sub(INUM + 1)?).ok()?)
+    }
+
+    pub(crate) fn lookup(&mut self, name: &str) -> nix::Result<u64> {
+        self.get_scan()?
+            .names
+            .get(name)
+            .and_then(|&i| u64::try_from(i).ok())
+            .map(|i| INUM + 1 + i)
+            .ok_or(nix::errno::Errno::ENOENT)
+    }
+
+    pub(crate) fn get_dirents(
+        &mut self,
+        dinum: u64,
+    ) -> nix::Result<Vec<libhammer2::hammer2::Dirent>> {
+        let scan = self.get_scan()?;
+        let mut v = vec![
+            libhammer2::hammer2::Dirent {
+                inum: INUM,
+                typ: libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY,
+                name: ".".to_string(),
+            },
+            libhammer2::hammer2::Dirent {
+                inum: dinum,
+                typ: libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY,
+                name: "..".to_string(),
+            },
+        ];
+        let mut l: Vec<_> = scan.names.iter().collect();
+        l.sort_by_key(|x| x.1);
+        for (name, &i) in l {
+            let Ok(x) = u64::try_from(i) else {
+                continue;
+            };
+            v.push(libhammer2::hammer2::Dirent {
+                inum: INUM + 1 + x,
+                typ: scan.inodes[i].typ,
+                name: name.clone(),
+            });
+        }
+        Ok(v)
+    }
+
+    // directory owned by owner of mount root
+    pub(crate) fn get_attr(
+        &mut self,
+        inum: u64,
+        root: &fuser::FileAttr,
+    ) -> nix::Result<fuser::FileAttr> {
+        if inum == INUM {
+            return Ok(fuser::FileAttr {
+                ino: INUM,
+                size: 0,
+                blocks: 0,
+                atime: self.time,
+                mtime: self.time,
+                ctime: self.time,
+                crtime: self.time,
+                kind: fuser::FileType::Directory,
+                perm: 0o500,
+                nlink: 2,
+                flags: 0,
+                ..*root
+            });
+        }
+        let ip = self.get_inode(inum).ok_or(nix::errno::Errno::ENOENT)?;
+        Ok(fuser::FileAttr {
+            ino: inum,
+            size: ip.size,
+            blocks: ip.size.div_ceil(512),
+            atime: crate::util::time2system(ip.atime),
+            mtime: crate::util::time2system(ip.mtime),
+            ctime: crate::util::time2system(ip.ctime),
+            crtime: crate::util::time2system(ip.btime),
+            kind: crate::util::obj2kind(ip.typ),
+            perm: (ip.mode & 0o777).try_into().or_nix_range()?,
+            nlink: 1,
+            uid: ip.uid,
+            gid: ip.gid,
+            rdev: 0,
+            blksize: libhammer2::fs::HAMMER2_PBUFSIZE.try_into().or_nix_range()?,
+            flags: crate::util::uflags2bsd(ip.uflags),
+        })
+    }
+
+    // holes and unreadable blocks are zero-filled
+    pub(crate) fn read(&mut self, inum: u64, size: u64, offset: u64) -> nix::Result<Vec<u8>> {
+        let i = inum
+            .checked_sub(INUM + 1)
+            .and_then(|x| usize::try_from(x).ok())
+            .ok_or(nix::errno::Errno::ENOENT)?;
+        let scan = self.get_scanned().ok_or(nix::errno::Errno::ENOENT)?;
+        let ip = scan.inodes.get_mut(i).ok_or(nix::errno::Errno::ENOENT)?;
+        if offset >= ip.size {
+            return Ok(vec![]);
+        }
+        let end = std::cmp::min(offset.saturating_add(size), ip.size);
+        if let Some(v) = &ip.data {
+            let beg = usize::try_from(offset).or_nix_range()?;
+            let end = usize::try_from(end).or_nix_range()?;
+            return Ok(v.get(beg..end).unwrap_or_default().to_vec());
+        }
+        let leaves = ip.leaves.get_or_insert_with(|| {
+            let mut v = vec![];
+            let mut visited = std::collections::HashSet::new();
+            get_leaves(
+                &mut *scan.src,
+                &ip.blockset,
+                ip.size,
+                0,
+                &mut visited,
+                &mut v,
+            );
+            v.sort_by_key(|x| x.key);
+            v
+        });
+        let mut buf = vec![0; usize::try_from(end - offset).or_nix_range()?];
+        let i = leaves.partition_point(|x| get_end(x).unwrap_or(u64::MAX) <= offset);
+        for bref in leaves[i..].iter().take_while(|x| x.key < end) {
+            let b = match read_block(&mut *scan.src, bref) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("inum {} key {:#x}: {e}", ip.inum, bref.key);
+                    continue;
+                }
+            };
+            let Some(x) = u64::try_from(b.len())
+                .ok()
+                .and_then(|n| bref.key.checked_add(n))
+            else {
+                continue;
+            };
+            let beg = std::cmp::max(bref.key, offset);
+            let x = std::cmp::min(x, end);
+            if beg < x {
+                let (Ok(dst), Ok(src)) = (
+                    usize::try_from(beg - offset),
+                    usize::try_from(beg - bref.key),
+                ) else {
+                    continue;
+                };
+                let n = usize::try_from(x - beg).or_nix_range()?;
+                buf[dst..dst + n].copy_from_slice(&b[src..src + n]);
+            }
+        }
+        Ok(buf)
+    }
+}
+
+// list unreachable inodes of given spec
+pub(crate) fn run(spec: &str, json: bool) -> crate::Result<()> {
+    let (special, selector, _) = crate::blksrc::parse_spec(spec);
+    let (helper, path) = crate::blkfuse::mount_special(special, selector)?;
+    let ret = find(special, selector, &path);
+    drop(helper);
+    let (_, l) = ret?;
+    let v: Vec<_> = l
+        .iter()
+        .map(|ip| {
+            serde_json::json!({
+                "inum": ip.inum,
+                "type": ip.typ,
+                "mode": ip.mode,
+                "uid": ip.uid,
+                "gid": ip.gid,
+                "size": ip.size,
+                "mtime": ip.mtime,
+                "iparent": ip.iparent,
+                "name": ip.name,
+                "offset": ip.offset,
+            })
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::Value::Array(v));
+    } else {
+        for ip in &l {
+            println!(
+                "{:<10} {:?} {:06o} {:>12} iparent {:<10} {}",
+                ip.inum,
+                crate::util::obj2kind(ip.typ),
+                ip.mode,
+                ip.size,
+                ip.iparent,
+                ip.name
+            );
+        }
+    }
+    Ok(())
+}
+
+impl crate::Hammer2Fuse {
+    pub(crate) fn get_lost_found_attr(&mut self, inum: u64) -> libhammer2::Result<fuser::FileAttr> {
+        let st = self.pmp.stat(self.root)?;
+        let meta = self.pmp.get_inode(self.root).map(|ip| ip.get_meta());
+        let root = crate::util::stat2attr(&st, meta, &self.idmap)?;
+        let Some(lf) = &mut self.lost_found else {
+            return Err(nix::errno::Errno::ENOENT.into());
+        };
+        let mut attr = lf.get_attr(inum, &root)?;
+        if inum != INUM {
+            self.idmap.map_attr(&mut attr);
+        }
+        Ok(attr)
+    }
+
+    pub(crate) fn read_lost_found(
+        &mut self,
+        inum: u64,
+        size: u64,
+        offset: u64,
+    ) -> nix::Result<Vec<u8>> {
+        match &mut self.lost_found {
+            Some(lf) => lf.read(inum, size, offset),
+            None => Err(nix::errno::Errno::ENOENT),
+        }
+    }
+
+    pub(crate) fn readdir_lost_found(
+        &mut self,
+    ) -> libhammer2::Result<Vec<libhammer2::hammer2::Dirent>> {
+        match &mut self.lost_found {
+            Some(lf) => Ok(lf.get_dirents(self.root)?),
+            None => Err(nix::errno::Errno::ENOENT.into()),
+        }
+    }
+
+    // Some if dinum / name is virtual
+    pub(crate) fn lookup_lost_found(
+        &mut self,
+        dinum: u64,
+        name: &std::ffi::OsStr,
+    ) -> Option<libhammer2::Result<u64>> {
+        let lf = self.lost_found.as_mut()?;
+        if dinum == self.root && name == NAME {
+            return Some(Ok(INUM));
+        }
+        if dinum != INUM {
+            return None;
+        }
+        Some(match name.to_str() {
+            Some("..") => Ok(self.root),
+            Some(".") => Ok(INUM),
+            Some(s) => lf.lookup(s).map_err(Into::into),
+            None => Err(nix::errno::Errno::ENOENT.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn get_inode(inum: u64, typ: u8, name: &[u8]) -> libhammer2::fs::Hammer2InodeData {
+        let b = vec![0; super::INODE_BYTES];
+        let mut ipdata = *libfs::cast::align_to::<libhammer2::fs::Hammer2InodeData>(&b);
+        ipdata.meta.version = libhammer2::fs::HAMMER2_INODE_VERSION_ONE;
+        ipdata.meta.typ = typ;
+        ipdata.meta.mode = 0o100_644;
+        ipdata.meta.inum = inum;
+        ipdata.meta.btime = 1234;
+        ipdata.meta.name_key = libhammer2::subs::dirhash(name);
+        ipdata.meta.name_len = u16::try_from(name.len()).unwrap();
+        ipdata.filename[..name.len()].copy_from_slice(name);
+        ipdata
+    }
+
+    fn get_blockref(keybits: u8, key: u64, data_off: u64) -> super::Blockref {
+        let b = vec![0; libhammer2::fs::HAMMER2_BLOCKREF_BYTES];
+        let mut bref = *libfs::cast::align_to::<super::Blockref>(&b);
+        bref.typ = libhammer2::fs::HAMMER2_BREF_TYPE_DATA;
+        bref.keybits = keybits;
+        bref.key = key;
+        bref.data_off = data_off;
+        bref
+    }
+
+    #[test]
+    fn test_parse_inode() {
+        let mut ipdata = get_inode(100, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE, b"file");
+        ipdata.meta.size = 5;
+        ipdata.meta.op_flags = libhammer2::fs::HAMMER2_OPFLAG_DIRECTDATA;
+        ipdata.u[..5].copy_from_slice(b"hello");
+        let ip = super::parse_inode(&ipdata, 4096).unwrap();
+        assert_eq!(ip.inum, 100);
+        assert_eq!(ip.typ, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE);
+        assert_eq!(ip.mode, 0o644);
+        assert_eq!(ip.btime, 1234);
+        assert_eq!(ip.name, "file");
+        assert_eq!(ip.offset, 4096);
+        assert_eq!(ip.data.as_deref(), Some(&b"hello"[..]));
+        assert!(ip.blockset.is_empty());
+    }
+
+    #[test]
+    fn test_parse_inode_blockset() {
+        let mut ipdata = get_inode(100, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE, b"file");
+        let bref = get_blockref(16, 0, 0x1_0000 | 16);
+        ipdata.u[..libhammer2::fs::HAMMER2_BLOCKREF_BYTES]
+            .copy_from_slice(libfs::cast::as_u8_slice(&bref));
+        let ip = super::parse_inode(&ipdata, 0).unwrap();
+        assert!(ip.data.is_none());
+        assert_eq!(ip.blockset.len(), 4);
+        let bref = ip.blockset[0];
+        assert_eq!(bref.typ, libhammer2::fs::HAMMER2_BREF_TYPE_DATA);
+        assert_eq!(super::get_psize(&bref), Some(0x1_0000));
+        assert_eq!(super::get_lsize(&bref), Some(0x1_0000));
+        assert_eq!(super::get_end(&bref), Some(0x1_0000));
+        // embedded data beyond inode
+        let mut ipdata = get_inode(100, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE, b"file");
+        ipdata.meta.size = 513;
+        ipdata.meta.op_flags = libhammer2::fs::HAMMER2_OPFLAG_DIRECTDATA;
+        assert!(super::parse_inode(&ipdata, 0).is_none());
+    }
+
+    #[test]
+    fn test_parse_inode_invalid() {
+        let ipdata = get_inode(100, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE, b"file");
+        assert!(super::parse_inode(&ipdata, 0).is_some());
+        let mut x = ipdata;
+        x.meta.version = 2;
+        assert!(super::parse_inode(&x, 0).is_none());
+        let mut x = ipdata;
+        x.meta.typ = 3;
+        assert!(super::parse_inode(&x, 0).is_none());
+        let mut x = ipdata;
+        x.meta.pfs_type = 1;
+        assert!(super::parse_inode(&x, 0).is_none());
+        let mut x = ipdata;
+        x.meta.name_key ^= 1 << 32;
+        assert!(super::parse_inode(&x, 0).is_none());
+        let mut x = ipdata;
+        x.meta.name_len = 0;
+        assert!(super::parse_inode(&x, 0).is_none());
+        let mut x = ipdata;
+        x.meta.name_len = 257;
+        assert!(super::parse_inode(&x, 0).is_none());
+        for (inum, name) in [(1, &b"file"[..]), (100, b"a/b"), (100, b"a\0b")] {
+            let x = get_inode(inum, libhammer2::fs::HAMMER2_OBJTYPE_REGFILE, name);
+            assert!(super::parse_inode(&x, 0).is_none());
+        }
+    }
+
+    #[test]
+    fn test_blockref_size() {
+        for (radix, keybits, psize, lsize) in [
+            (10, 10, Some(1 << 10), Some(1 << 10)),
+            (16, 16, Some(1 << 16), Some(1 << 16)),
+            (9, 17, None, None),
+            (17, 63, None, None),
+            (0x3F, 64, None, None),
+        ] {
+            let bref = get_blockref(keybits, 0, 0x1000 | radix);
+            assert_eq!(super::get_psize(&bref), psize, "{radix}");
+            assert_eq!(super::get_lsize(&bref), lsize, "{keybits}");
+        }
+        assert!(super::get_end(&get_blockref(10, u64::MAX, 0x1000 | 10)).is_none());
+    }
+}
@@ -1,5 +1,31 @@
+mod audit;
+mod blkfuse;
+mod blksrc;
+mod cache;
+mod config;
+mod datacache;
+mod dirent;
+mod fold;
+mod follow;
 mod fuse;
+mod handle;
+mod idmap;
 mod ioctl;
+mod logger;
+mod lostfound;
+mod namei;
+mod nlookup;
+mod option;
+mod part;
+mod perm;
+mod pfs;
+mod probe;
+mod prune;
+mod readahead;
+mod reqlog;
+mod salvage;
+mod scan;
+mod subdir;
 mod util;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -9,16 +35,55 @@ const HAMMER2_CIDALLOC: &str = "HAMMER2_CIDALLOC";
 
 struct Hammer2Fuse {
     pmp: libhammer2::hammer2::Hammer2,
+    root: u64,
     total_open: usize,
+    datacache: Option<datacache::DataCache>,
+    prune: prune::Monitor,
+    handles: handle::Handles,
+    names: std::collections::HashMap<u64, (u64, std::ffi::OsString)>,
+    nlookup: std::collections::HashMap<u64, u64>,
+    follow: Option<follow::Follow>,
+    salvage: salvage::SalvageMode,
+    lost_found: Option<lostfound::LostFound>,
+    fold: fold::Fold,
+    idmap: idmap::IdMaps,
+    attr_ttl: std::time::Duration,
+    entry_ttl: std::time::Duration,
+    default_permissions: bool,
+    cache: cache::Policy,
     debug: i32,
     daemonized: bool,
 }
 
 impl Hammer2Fuse {
-    fn new(pmp: libhammer2::hammer2::Hammer2, debug: i32, daemonized: bool) -> Self {
+    fn new(
+        pmp: libhammer2::hammer2::Hammer2,
+        root: u64,
+        opt: &option::Opt,
+        debug: i32,
+        daemonized: bool,
+    ) -> Self {
         Self {
             pmp,
+            root,
             total_open: 0,
+            datacache: opt
+                .datacache_size
+                .filter(|&v| v > 0 && !opt.nodatacache)
+                .map(datacache::DataCache::new),
+            prune: prune::Monitor::new(opt),
+            handles: handle::Handles::new(opt),
+            names: std::collections::HashMap::new(),
+            nlookup: std::collections::HashMap::new(),
+            follow: None,
+            salvage: opt.salvage,
+            lost_found: None,
+            fold: fold::Fold::new(opt),
+            idmap: idmap::IdMaps::new(opt),
+            attr_ttl: opt.attr_timeout.unwrap_or(fuse::TTL),
+            entry_ttl: opt.entry_timeout.unwrap_or(fuse::TTL),
+            default_permissions: !opt.nodefault_permissions,
+            cache: cache::Policy::new(opt),
             debug,
             daemonized,
         }
@@ -37,7 +102,7 @@ fn init_std_logger() -> std::result::Result<(), log::SetLoggerError> {
     env_logger::try_init_from_env(env)
 }
 
-fn init_file_logger(prog: &str) -> Result<()> {
+fn init_file_logger(prog: &str, opt: &option::Opt) -> Result<()> {
     let dir = util::get_home_path()?;
     let name = format!(
         ".{}.log",
@@ -46,27 +111,27 @@ fn init_file_logger(prog: &str) -> Result<()> {
             None => "hammer2-fuse".to_string(),
         }
     );
-    let f = match std::env::var(HAMMER2_HOME) {
-        Ok(v) => if libfs::fs::is_dir(&v) {
-            libfs::fs::join_path(&v, &name)
-        } else {
-            eprintln!("{HAMMER2_HOME} not a directory, using {dir} instead");
-            libfs::fs::join_path(&dir, &name)
-        }
-        .ok_or(nix::errno::Errno::EINVAL)?,
-        Err(_) => return Err(Box::new(nix::errno::Errno::ENOENT)),
-    };
-    Ok(simplelog::CombinedLogger::init(vec![
-        simplelog::WriteLogger::new(
-            if libfs::is_debug_set() {
-                simplelog::LevelFilter::Trace
+    let f = match &opt.log_file {
+        Some(v) => v.clone(),
+        None => match std::env::var(HAMMER2_HOME) {
+            Ok(v) => if libfs::fs::is_dir(&v) {
+                libfs::fs::join_path(&v, &name)
             } else {
-                simplelog::LevelFilter::Info
-            },
-            simplelog::Config::default(),
-            std::fs::File::create(f)?,
-        ),
-    ])?)
+                eprintln!("{HAMMER2_HOME} not a directory, using {dir} instead");
+                libfs::fs::join_path(&dir, &name)
+            }
+            .ok_or(nix::errno::Errno::EINVAL)?,
+            Err(_) => return Err(Box::new(nix::errno::Errno::ENOENT)),
+        },
+    };
+    let logger = logger::FileLogger::new(
+        &f,
+        opt.log_max_size.unwrap_or(logger::DEFAULT_MAX_SIZE),
+        opt.log_rotate.unwrap_or(logger::DEFAULT_ROTATE),
+        opt.log_json,
+    )?;
+    Ok(log::set_boxed_logger(Box::new(logger))
+        .map(|()| log::set_max_level(logger::get_default_level()))?)
 }
 
 fn init_syslog_logger(prog: &str) -> Result<()> {
@@ -81,21 +146,17 @@ fn init_syslog_logger(prog: &str) -> Result<()> {
     };
     let logger = syslog::unix(formatter)?;
     Ok(
-        log::set_boxed_logger(Box::new(syslog::BasicLogger::new(logger))).map(|()| {
-            log::set_max_level(if libfs::is_debug_set() {
-                //log::LevelFilter::Trace // XXX not traced
-                log::LevelFilter::Info
-            } else {
-                log::LevelFilter::Info
-            });
-        })?,
+        log::set_boxed_logger(Box::new(syslog::BasicLogger::new(logger)))
+            .map(|()| log::set_max_level(logger::get_default_level()))?,
     )
 }
 
 fn usage(prog: &str, gopt: &getopts::Options) {
     print!(
         "{}",
-        gopt.usage(&format!("Usage: {prog} [options] special[@label] node"))
+        gopt.usage(&format!(
+            "Usage: {prog} [options] special[#part][@label|@clid=UUID|@fsid=UUID[:/path]] node"
+        ))
     );
 }
 
@@ -111,45 +172,48 @@ fn main() {
 
 #[allow(clippy::too_many_lines)]
 fn main_impl() -> Result<()> {
-    println!(
-        "FUSE hammer2 {}.{}.{} (fuser)",
-        libhammer2::VERSION[0],
-        libhammer2::VERSION[1],
-        libhammer2::VERSION[2]
-    );
-
     let args: Vec<String> = std::env::args().collect();
     let prog = &args[0];
 
     let mut gopt = getopts::Options::new();
-    // https://docs.rs/fuser/latest/fuser/enum.MountOption.html
-    gopt.optflag(
+    gopt.optflag("d", "", "Enable env_logger logging and do not daemonize.");
+    gopt.optopt(
         "",
-        "allow_other",
-        "Allow all users to access files on this filesystem. \
-        By default access is restricted to the user who mounted it.",
+        "config",
+        "Configuration file to use instead of system-wide and $HAMMER2_HOME ones.",
+        "PATH",
+    );
+    gopt.optmulti(
+        "o",
+        "",
+        "Comma separated mount options as used by mount(8) and fstab(5), \
+        e.g. allow_other,noexec,label=ROOT,attr_timeout=1.",
+        "OPTIONS",
+    );
+    option::init_getopts(&mut gopt);
+    gopt.optflagopt(
+        "",
+        "lost_found_list",
+        "Print deleted inodes found on given special (text or json) and exit.",
+        "FORMAT",
     );
     gopt.optflag(
         "",
-        "allow_root",
-        "Allow the root user to access this filesystem, \
-        in addition to the user who mounted it.",
+        "scan",
+        "Assemble special from HAMMER2 volumes in given directory or glob.",
     );
-    gopt.optflag("", "noexec", "Dont allow execution of binaries.");
-    if libfs::os::is_linux() {
-        gopt.optflag(
-            "",
-            "auto_unmount",
-            "Automatically unmount when the mounting process exits. \
-            AutoUnmount requires AllowOther or AllowRoot. \
-            If AutoUnmount is set and neither Allow... is set, \
-            the FUSE configuration must permit allow_other, \
-            otherwise mounting will fail. \
-            Available on Linux.",
-        );
-    }
-    gopt.optflag("d", "", "Enable env_logger logging and do not daemonize.");
-    gopt.optflag("", "nodatacache", "Disable decompressed data cache.");
+    gopt.optflagopt(
+        "",
+        "probe",
+        "Print volume headers and PFS of given specials (text or json) and exit.",
+        "FORMAT",
+    );
+    // mount(8) invokes mount.<type> helpers with these
+    gopt.optflag("s", "", "Ignore unknown mount options.");
+    gopt.optflag("f", "", "Do everything except the actual mount.");
+    gopt.optflag("n", "", "Ignored for mount(8) compatibility.");
+    gopt.optflag("v", "", "Ignored for mount(8) compatibility.");
+    gopt.optopt("t", "", "Ignored for mount(8) compatibility.", "TYPE");
     gopt.optflag("V", "version", "Print version and copyright.");
     gopt.optflag("h", "help", "Print usage.");
 
@@ -161,6 +225,14 @@ fn main_impl() -> Result<()> {
             return Err(Box::new(e));
         }
     };
+    if !matches.opt_present("probe") && !matches.opt_present("lost_found_list") {
+        println!(
+            "FUSE hammer2 {}.{}.{} (fuser)",
+            libhammer2::VERSION[0],
+            libhammer2::VERSION[1],
+            libhammer2::VERSION[2]
+        );
+    }
     if matches.opt_present("V") {
         return Ok(());
     }
@@ -170,35 +242,95 @@ fn main_impl() -> Result<()> {
     }
 
     let args = &matches.free;
+    if matches.opt_present("probe") {
+        if args.is_empty() {
+            usage(prog, &gopt);
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        if matches.opt_present("d") {
+            init_std_logger()?;
+        }
+        let json = match matches.opt_str("probe").as_deref() {
+            None | Some("text") => false,
+            Some("json") => true,
+            Some(v) => {
+                eprintln!("invalid probe format {v}");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+        };
+        return probe::run(args, json);
+    }
+    if matches.opt_present("lost_found_list") {
+        if args.len() != 1 {
+            usage(prog, &gopt);
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        if matches.opt_present("d") {
+            init_std_logger()?;
+        }
+        let json = match matches.opt_str("lost_found_list").as_deref() {
+            None | Some("text") => false,
+            Some("json") => true,
+            Some(v) => {
+                eprintln!("invalid lost_found_list format {v}");
+                return Err(Box::new(nix::errno::Errno::EINVAL));
+            }
+        };
+        return lostfound::run(&args[0], json);
+    }
     if args.len() != 2 {
         usage(prog, &gopt);
         return Err(Box::new(nix::errno::Errno::EINVAL));
     }
-    let spec = &args[0];
+    // label may be given by -o, hence parse command line options twice
+    let mut opt = option::Opt::default();
+    opt.parse_args(&matches)?;
+    let (spec, subdir) = subdir::split_spec(&opt.get_spec(&args[0])?);
+    let spec = &if matches.opt_present("scan") {
+        let v = scan::assemble(&spec)?;
+        println!("{v}");
+        v
+    } else {
+        spec
+    };
     let mntpt = &args[1];
 
+    let cfg = config::load(matches.opt_str("config").as_deref(), spec)?;
+    opt = option::Opt::default();
+    for x in &cfg {
+        opt.apply_config(x)?;
+    }
+    if let Ok(v) = std::env::var(HAMMER2_CIDALLOC)
+        && !v.is_empty()
+    {
+        opt.cidalloc = Some(v);
+    }
+    opt.parse_args(&matches)?;
+
     let mut fopt = vec![
-        fuser::MountOption::FSName(spec.clone()),
+        fuser::MountOption::FSName(opt.fsname.clone().unwrap_or_else(|| spec.clone())),
         fuser::MountOption::Subtype("hammer2".to_string()),
-        fuser::MountOption::DefaultPermissions,
         #[cfg(target_os = "linux")]
         fuser::MountOption::NoDev,
         fuser::MountOption::NoSuid,
     ];
+    if !opt.nodefault_permissions {
+        fopt.push(fuser::MountOption::DefaultPermissions);
+    }
     let mut mopt = vec![];
     // https://docs.rs/fuser/latest/fuser/enum.MountOption.html
-    if matches.opt_present("allow_other") {
+    if opt.allow_other {
         fopt.push(fuser::MountOption::AllowOther);
     }
-    if matches.opt_present("allow_root") {
+    if opt.allow_root {
         fopt.push(fuser::MountOption::AllowRoot);
     }
-    if matches.opt_present("noexec") {
+    if opt.noexec {
         fopt.push(fuser::MountOption::NoExec);
     } else {
         fopt.push(fuser::MountOption::Exec);
     }
-    if libfs::os::is_linux() && matches.opt_present("auto_unmount") {
+    if opt.auto_unmount {
         fopt.push(fuser::MountOption::AutoUnmount);
     }
     let use_daemon = !matches.opt_present("d"); // not debug
@@ -207,36 +339,96 @@ fn main_impl() -> Result<()> {
         mopt.push("--debug");
     }
 
-    if matches.opt_present("nodatacache") {
+    // bounded cache replaces libhammer2 one
+    if opt.nodatacache || opt.datacache_size.is_some() {
         mopt.extend_from_slice(&["--nodatacache"]);
     }
 
-    let cidalloc = std::env::var(HAMMER2_CIDALLOC).unwrap_or_default();
-    if !cidalloc.is_empty() {
-        mopt.extend_from_slice(&["--cidalloc", &cidalloc]);
+    if let Some(v) = &opt.cidalloc {
+        mopt.extend_from_slice(&["--cidalloc", v]);
+    }
+
+    if matches.opt_present("f") {
+        return Ok(());
     }
 
+    let mut file_logger = false;
     if !use_daemon {
         if let Err(e) = init_std_logger() {
             eprintln!("{e}");
             return Err(Box::new(e));
         }
-    } else if init_file_logger(prog).is_err()
-        && let Err(e) = init_syslog_logger(prog)
-    {
+    } else if !opt.syslog && init_file_logger(prog, &opt).is_ok() {
+        file_logger = true;
+    } else if let Err(e) = init_syslog_logger(prog) {
         eprintln!("syslog logger: {e}");
     }
+    if opt.log_json {
+        reqlog::enable();
+    }
+    if let Some(v) = &opt.audit_log
+        && let Err(e) = audit::init(v)
+    {
+        log::error!("{v}: {e}");
+        eprintln!("{v}: {e}");
+        return Err(Box::new(e));
+    }
+    if file_logger && let Err(e) = logger::init_signal() {
+        log::error!("{e}");
+        return Err(Box::new(e));
+    }
 
-    let pmp = match libhammer2::mount(spec, &mopt) {
-        Ok(v) => v,
-        Err(e) => {
-            log::error!("{e}");
-            if use_daemon {
-                eprintln!("{e}");
+    // container special is opened and mounted here to report errors,
+    // and mounted again after daemonize via helper mount
+    let (special, selector, label) = blksrc::parse_spec(spec);
+    let src = if special.contains(':') && selector.is_none() {
+        None // multiple volumes
+    } else {
+        match blksrc::open(special, selector) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{special}: {e}");
+                if use_daemon {
+                    eprintln!("{special}: {e}");
+                }
+                return Err(Box::new(e));
             }
-            return Err(Box::new(e));
         }
     };
+    let pmp = if src.is_none() {
+        let spec = match label {
+            Some(v) => format!("{special}@{v}"),
+            None => special.to_string(),
+        };
+        match mount_hammer2(&spec, &mopt, use_daemon) {
+            Ok(v) => Some((v, spec)),
+            // retry with older volume headers after daemonize
+            Err(e) if opt.salvage != salvage::SalvageMode::Off && !special.contains(':') => {
+                log::warn!("{spec}: {e}");
+                None
+            }
+            Err(e) => return Err(e),
+        }
+    } else {
+        None
+    };
+    // helper threads don't survive fork, salvage mode falls back later
+    let src = match src {
+        Some(src) if use_daemon && opt.salvage == salvage::SalvageMode::Off => {
+            let h = match blkfuse::mount(src) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{e}");
+                    eprintln!("{e}");
+                    return Err(Box::new(e));
+                }
+            };
+            mount_hammer2(&h.get_spec(label), &mopt, use_daemon)?.unmount()?;
+            drop(h);
+            blksrc::open(special, selector)?
+        }
+        v => v,
+    };
     fopt.push(fuser::MountOption::RO);
     log::debug!("{fopt:?}");
 
@@ -248,16 +440,116 @@ fn main_impl() -> Result<()> {
             return Err(Box::new(e));
         }
     }
-    // fuser::mount2 doesn't return, hence after daemonize
+    let mut helper = None;
+    let (mut pmp, mspec) = match (pmp, src) {
+        (Some(v), _) => v,
+        (None, Some(src)) => {
+            let h = match blkfuse::mount(src) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{e}");
+                    return Err(Box::new(e));
+                }
+            };
+            let spec = h.get_spec(label);
+            match mount_hammer2(&spec, &mopt, false) {
+                Ok(v) => {
+                    helper = Some(h);
+                    (v, spec)
+                }
+                Err(e) if opt.salvage != salvage::SalvageMode::Off => {
+                    log::warn!("{spec}: {e}");
+                    drop(h);
+                    let (v, h, spec) = salvage::mount(special, selector, label, &mopt)?;
+                    helper = Some(h);
+                    (v, spec)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        (None, None) => {
+            let (v, h, spec) = salvage::mount(special, selector, label, &mopt)?;
+            helper = Some(h);
+            (v, spec)
+        }
+    };
+    let root = match &subdir {
+        Some(v) => match subdir::resolve(&mut pmp, v) {
+            Ok(x) => {
+                log::info!("{v}: inum {x}");
+                x
+            }
+            Err(e) => {
+                log::error!("{v}: {e}");
+                return Err(e);
+            }
+        },
+        None => libhammer2::inode::INUM_PFS_ROOT,
+    };
+    // fuser::Session::run doesn't return, hence after daemonize
     // XXX use fuser::spawn_mount2
-    if let Err(e) = fuser::mount2(
-        Hammer2Fuse::new(pmp, libfs::get_debug_level(), use_daemon),
-        mntpt,
-        &fopt,
-    ) {
+    let mut fs = Hammer2Fuse::new(pmp, root, &opt, libfs::get_debug_level(), use_daemon);
+    if opt.lost_found {
+        // PFS are walked via helper path if any
+        let path = match &helper {
+            Some(h) => h.get_path(),
+            None => special.to_string(),
+        };
+        fs.lost_found = Some(lostfound::LostFound::new(special, selector, &path));
+    }
+    if opt.follow {
+        fs.follow = Some(follow::Follow::new(&mspec, subdir.as_deref(), &mopt));
+        follow::spawn(
+            special,
+            selector,
+            opt.follow_interval.unwrap_or(follow::DEFAULT_INTERVAL),
+        );
+    }
+    let mut session = match fuser::Session::new(fs, mntpt, &fopt) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{e}");
+            return Err(Box::new(e));
+        }
+    };
+    cache::set_notifier(session.notifier());
+    let ret = session.run();
+    drop(session); // unmount libhammer2 before helper
+    drop(helper);
+    if let Err(e) = salvage::write_report(opt.damage_report.as_deref()) {
+        log::error!("{e}");
+    }
+    if let Err(e) = ret {
         log::error!("{e}");
         return Err(Box::new(e));
     }
 
     Ok(())
 }
+
+fn mount_hammer2(
+    spec: &str,
+    mopt: &[&str],
+    use_daemon: bool,
+) -> Result<libhammer2::hammer2::Hammer2> {
+    let spec = match pfs::resolve_spec(spec, mopt) {
+        Ok(v) => v.unwrap_or_else(|| spec.to_string()),
+        Err(e) => {
+            log::error!("{e}");
+            if use_daemon {
+                eprintln!("{e}");
+            }
+            return Err(e);
+        }
+    };
+    match libhammer2::mount(&spec, mopt) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            log::error!("{e}");
+            if use_daemon {
+                eprintln!("{e}");
+            }
+            Err(Box::new(e))
+        }
+    }
+}
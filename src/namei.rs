@@ -0,0 +1,47 @@
+// inode number to path, learned from lookup and readdir,
+// kept only while looked up by kernel or open
+const MAX_DEPTH: usize = 1024;
+
+impl crate::Hammer2Fuse {
+    fn is_referenced(&self, inum: u64) -> bool {
+        self.nlookup.contains_key(&inum) || self.handles.has_inum(inum)
+    }
+
+    pub(crate) fn cache_name(&mut self, dinum: u64, name: &std::ffi::OsStr, inum: u64) {
+        if inum != dinum && name != "." && name != ".." && self.is_referenced(inum) {
+            self.names.insert(inum, (dinum, name.to_os_string()));
+        }
+    }
+
+    // called when lookup count or open handle is dropped
+    pub(crate) fn uncache_name(&mut self, inum: u64) {
+        if !self.is_referenced(inum) {
+            self.names.remove(&inum);
+        }
+    }
+
+    pub(crate) fn get_path(&self, inum: u64) -> String {
+        let mut v = vec![];
+        let mut inum = inum;
+        while inum != self.root {
+            let Some((dinum, name)) = self.names.get(&inum) else {
+                // unknown ancestor
+                v.push(format!("<{inum}>").into());
+                break;
+            };
+            if v.len() >= MAX_DEPTH {
+                break;
+            }
+            v.push(name.clone());
+            inum = *dinum;
+        }
+        if v.is_empty() {
+            return "/".to_string();
+        }
+        let mut path = std::path::PathBuf::from("/");
+        for x in v.iter().rev() {
+            path.push(x);
+        }
+        path.to_string_lossy().into_owned()
+    }
+}
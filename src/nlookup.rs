@@ -0,0 +1,52 @@
+// kernel lookup count per inode, an inode is referenced while looked up
+impl crate::Hammer2Fuse {
+    pub(crate) fn inc_lookup(&mut self, inum: u64) -> libhammer2::Result<()> {
+        if inum == self.root {
+            return Ok(()); // never forgotten
+        }
+        if let Some(n) = self.nlookup.get_mut(&inum) {
+            *n += 1;
+            return Ok(());
+        }
+        match self.pmp.get_inode_mut(inum) {
+            Some(ip) => ip.get()?,
+            None => return Err(nix::errno::Errno::ENOENT.into()),
+        }
+        self.nlookup.insert(inum, 1);
+        Ok(())
+    }
+
+    pub(crate) fn dec_lookup(&mut self, inum: u64, nlookup: u64) {
+        let Some(n) = self.nlookup.get_mut(&inum) else {
+            return;
+        };
+        *n = n.saturating_sub(nlookup);
+        if *n > 0 {
+            return;
+        }
+        self.nlookup.remove(&inum);
+        self.uncache_name(inum);
+        self.fold.remove(inum);
+        if let Some(dc) = &mut self.datacache
+            && let Err(e) = dc.remove_inode(inum)
+        {
+            log::error!("inum {inum}: {e}");
+        }
+        match self.pmp.get_inode_mut(inum) {
+            Some(ip) => {
+                if let Err(e) = ip.put() {
+                    log::error!("inum {inum}: {e}");
+                }
+            }
+            None => log::error!("inum {inum} not found"),
+        }
+    }
+
+    pub(crate) fn put_lookup_all(&mut self) {
+        let v: Vec<_> = self.nlookup.iter().map(|(&k, &v)| (k, v)).collect();
+        for (inum, n) in v {
+            self.dec_lookup(inum, n);
+        }
+        assert!(self.nlookup.is_empty());
+    }
+}
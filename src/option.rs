@@ -0,0 +1,860 @@
+// mount(8) / fstab style "-o opt[,opt...]" option strings
+const IGNORED: [&str; 11] = [
+    "_netdev", "auto", "noauto", "user", "users", "nouser", "owner", "group", "nofail", "defaults",
+    "ro",
+];
+
+#[derive(Debug, Default)]
+pub(crate) struct Opt {
+    pub(crate) allow_other: bool,
+    pub(crate) allow_root: bool,
+    pub(crate) noexec: bool,
+    pub(crate) auto_unmount: bool,
+    pub(crate) nodatacache: bool,
+    pub(crate) nodefault_permissions: bool,
+    pub(crate) casefold: bool,
+    pub(crate) normalize: bool,
+    pub(crate) follow: bool,
+    pub(crate) salvage: crate::salvage::SalvageMode,
+    pub(crate) lost_found: bool,
+    pub(crate) fsname: Option<String>,
+    pub(crate) label: Option<String>,
+    pub(crate) cidalloc: Option<String>,
+    pub(crate) attr_timeout: Option<std::time::Duration>,
+    pub(crate) entry_timeout: Option<std::time::Duration>,
+    pub(crate) readahead: Option<u64>,
+    pub(crate) datacache_size: Option<u64>,
+    pub(crate) cache_mode: crate::cache::CacheMode,
+    pub(crate) cache_threshold: Option<u64>,
+    pub(crate) prune_rss: Option<u64>,
+    pub(crate) prune_inodes: Option<usize>,
+    pub(crate) prune_psi: Option<f64>,
+    pub(crate) prune_cgroup: bool,
+    pub(crate) follow_interval: Option<std::time::Duration>,
+    pub(crate) damage_report: Option<String>,
+    pub(crate) log_file: Option<String>,
+    pub(crate) log_max_size: Option<u64>,
+    pub(crate) log_rotate: Option<usize>,
+    pub(crate) log_json: bool,
+    pub(crate) syslog: bool,
+    pub(crate) audit_log: Option<String>,
+    pub(crate) uidmap: crate::idmap::IdMap,
+    pub(crate) gidmap: crate::idmap::IdMap,
+}
+
+// option value from command line, -o or configuration file
+#[derive(Clone, Copy, Debug)]
+enum Value<'a> {
+    Flag(bool),
+    Str(&'a str),
+}
+
+impl<'a> Value<'a> {
+    fn get_flag(self) -> nix::Result<bool> {
+        match self {
+            Self::Flag(v) => Ok(v),
+            Self::Str(_) => Err(nix::errno::Errno::EINVAL),
+        }
+    }
+
+    fn get_str(self) -> nix::Result<&'a str> {
+        match self {
+            Self::Str(v) => Ok(v),
+            Self::Flag(_) => Err(nix::errno::Errno::EINVAL),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Arg {
+    No,
+    Required,
+    Optional,
+}
+
+// where an option is accepted
+const CMDLINE: u8 = 1 << 0;
+const MOUNTOPT: u8 = 1 << 1;
+pub(crate) const CONFIG: u8 = 1 << 2;
+const ANY: u8 = CMDLINE | MOUNTOPT | CONFIG;
+
+pub(crate) struct Desc {
+    name: &'static str,
+    arg: Arg,
+    hint: &'static str,
+    help: &'static str,
+    src: u8,
+    linux: bool, // unknown elsewhere, ignored in configuration file
+    set: fn(&mut Opt, Value<'_>) -> nix::Result<()>,
+}
+
+// https://docs.rs/fuser/latest/fuser/enum.MountOption.html
+const OPTIONS: &[Desc] = &[
+    Desc {
+        name: "allow_other",
+        arg: Arg::No,
+        hint: "",
+        help: "Allow all users to access files on this filesystem. \
+            By default access is restricted to the user who mounted it.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.allow_other = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "allow_root",
+        arg: Arg::No,
+        hint: "",
+        help: "Allow the root user to access this filesystem, \
+            in addition to the user who mounted it.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.allow_root = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "noexec",
+        arg: Arg::No,
+        hint: "",
+        help: "Dont allow execution of binaries.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.noexec = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "exec",
+        arg: Arg::No,
+        hint: "",
+        help: "Allow execution of binaries.",
+        src: MOUNTOPT,
+        linux: false,
+        set: |opt, v| {
+            opt.noexec = !v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "auto_unmount",
+        arg: Arg::No,
+        hint: "",
+        help: "Automatically unmount when the mounting process exits. \
+            AutoUnmount requires AllowOther or AllowRoot. \
+            If AutoUnmount is set and neither Allow... is set, \
+            the FUSE configuration must permit allow_other, \
+            otherwise mounting will fail. \
+            Available on Linux.",
+        src: ANY,
+        linux: true,
+        set: |opt, v| {
+            opt.auto_unmount = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "fsname",
+        arg: Arg::Required,
+        hint: "NAME",
+        help: "File system name shown in mount table instead of special.",
+        src: MOUNTOPT,
+        linux: false,
+        set: |opt, v| {
+            opt.fsname = Some(v.get_str()?.to_string());
+            Ok(())
+        },
+    },
+    Desc {
+        name: "label",
+        arg: Arg::Required,
+        hint: "LABEL",
+        help: "PFS label, same as special@label.",
+        src: MOUNTOPT,
+        linux: false,
+        set: |opt, v| {
+            opt.label = Some(v.get_str()?.to_string());
+            Ok(())
+        },
+    },
+    Desc {
+        name: "cidalloc",
+        arg: Arg::Required,
+        hint: "ALLOC",
+        help: "libhammer2 chain allocator, also taken from $HAMMER2_CIDALLOC.",
+        src: MOUNTOPT | CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.cidalloc = Some(v.get_str()?.to_string());
+            Ok(())
+        },
+    },
+    Desc {
+        name: "attr_timeout",
+        arg: Arg::Required,
+        hint: "SECS",
+        help: "Kernel attribute cache timeout.",
+        src: MOUNTOPT | CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.attr_timeout = Some(parse_timeout(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "entry_timeout",
+        arg: Arg::Required,
+        hint: "SECS",
+        help: "Kernel directory entry cache timeout.",
+        src: MOUNTOPT | CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.entry_timeout = Some(parse_timeout(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "log_format",
+        arg: Arg::Required,
+        hint: "FORMAT",
+        help: "Log format, either text or json. \
+            json also logs each FUSE request with its caller, errno and duration.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.log_json = parse_log_format(v.get_str()?)?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "log_file",
+        arg: Arg::Required,
+        hint: "PATH",
+        help: "Log file when daemonized.",
+        src: CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.log_file = Some(v.get_str()?.to_string());
+            Ok(())
+        },
+    },
+    Desc {
+        name: "log_max_size",
+        arg: Arg::Required,
+        hint: "BYTES",
+        help: "Rotate log file at this many bytes.",
+        src: CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.log_max_size = Some(parse_size(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "log_rotate",
+        arg: Arg::Required,
+        hint: "COUNT",
+        help: "Number of rotated log files to keep, 0 truncates.",
+        src: CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.log_rotate = Some(parse_count(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "syslog",
+        arg: Arg::No,
+        hint: "",
+        help: "Log to syslog instead of log file when daemonized.",
+        src: CONFIG,
+        linux: false,
+        set: |opt, v| {
+            opt.syslog = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "audit_log",
+        arg: Arg::Required,
+        hint: "PATH",
+        help: "Append open, opendir, readlink and ioctl requests with the caller \
+            and the result to this file.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.audit_log = Some(v.get_str()?.to_string());
+            Ok(())
+        },
+    },
+    Desc {
+        name: "uidmap",
+        arg: Arg::Required,
+        hint: "MAP",
+        help: "Remap file owners, either \"squash\" to the mounting user, \
+            from/to[/count] ranges separated by \":\", \
+            or a file with \"from to [count]\" per line.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.uidmap = parse_uidmap(v.get_str()?)?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "gidmap",
+        arg: Arg::Required,
+        hint: "MAP",
+        help: "Remap file groups in the same format as --uidmap.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.gidmap = parse_gidmap(v.get_str()?)?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "nodefault_permissions",
+        arg: Arg::No,
+        hint: "",
+        help: "Check permissions in this filesystem with remapped ownership, \
+            supplementary groups of the caller and immutable / append-only flags, \
+            instead of the kernel.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.nodefault_permissions = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "default_permissions",
+        arg: Arg::No,
+        hint: "",
+        help: "Check permissions in the kernel (default).",
+        src: MOUNTOPT,
+        linux: false,
+        set: |opt, v| {
+            opt.nodefault_permissions = !v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "readahead",
+        arg: Arg::Required,
+        hint: "BYTES",
+        help: "Maximum read-ahead window in bytes on sequential read, 0 to disable, \
+            capped at 8MiB. The window is read synchronously.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.readahead = Some(check_readahead(parse_size(v.get_str()?)?));
+            Ok(())
+        },
+    },
+    Desc {
+        name: "nodatacache",
+        arg: Arg::No,
+        hint: "",
+        help: "Disable decompressed data cache.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.nodatacache = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "datacache_size",
+        arg: Arg::Required,
+        hint: "BYTES",
+        help: "Bound decompressed data cache to this many bytes with LRU eviction, \
+            0 disables.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.datacache_size = Some(parse_size(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "casefold",
+        arg: Arg::No,
+        hint: "",
+        help: "Retry failed lookup ignoring case.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.casefold = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "normalize",
+        arg: Arg::No,
+        hint: "",
+        help: "Retry failed lookup comparing NFC normalized names.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.normalize = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "cache",
+        arg: Arg::Required,
+        hint: "MODE",
+        help: "Page cache mode for open files (keep, direct or adaptive).",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.cache_mode = crate::cache::CacheMode::new(v.get_str()?)?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "cache_threshold",
+        arg: Arg::Required,
+        hint: "BYTES",
+        help: "Keep page cache for files of this many bytes or larger in adaptive mode.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.cache_threshold = Some(parse_size(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "prune_rss",
+        arg: Arg::Required,
+        hint: "BYTES",
+        help: "Prune chains when RSS exceeds this many bytes.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.prune_rss = Some(parse_size(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "prune_inodes",
+        arg: Arg::Required,
+        hint: "COUNT",
+        help: "Prune chains after this many inodes are looked up.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.prune_inodes = Some(parse_count(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "prune_psi",
+        arg: Arg::Required,
+        hint: "PCT",
+        help: "Prune chains when memory pressure (some avg10) exceeds this percentage.",
+        src: ANY,
+        linux: true,
+        set: |opt, v| {
+            opt.prune_psi = Some(parse_psi(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "prune_cgroup",
+        arg: Arg::No,
+        hint: "",
+        help: "Prune chains on cgroup memory.events high / max events.",
+        src: ANY,
+        linux: true,
+        set: |opt, v| {
+            opt.prune_cgroup = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "follow",
+        arg: Arg::No,
+        hint: "",
+        help: "Remount when volume header of the backing image gets a newer mirror_tid.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.follow = v.get_flag()?;
+            Ok(())
+        },
+    },
+    Desc {
+        name: "follow_interval",
+        arg: Arg::Required,
+        hint: "SECS",
+        help: "Poll volume header every this many seconds with --follow (default 5).",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.follow_interval = Some(parse_interval(v.get_str()?)?);
+            Ok(())
+        },
+    },
+    Desc {
+        name: "salvage",
+        arg: Arg::Optional,
+        hint: "MODE",
+        help: "Emergency mode for damaged media (skip or zero, default skip). Both fall \
+            back to older volume headers and list readable part of directories, \
+            zero also zero-fills unreadable data blocks.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.salvage = match v {
+                Value::Flag(true) => crate::salvage::SalvageMode::Skip,
+                Value::Flag(false) => crate::salvage::SalvageMode::Off,
+                Value::Str(v) => crate::salvage::SalvageMode::new(v)?,
+            };
+            Ok(())
+        },
+    },
+    Desc {
+        name: "damage_report",
+        arg: Arg::Required,
+        hint: "PATH",
+        help: "Write damage found in salvage mode to this file on unmount \
+            (default $HAMMER2_HOME/.hammer2-fuse.damage.json).",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.damage_report = Some(v.get_str()?.to_string());
+            Ok(())
+        },
+    },
+    Desc {
+        name: "lost_found",
+        arg: Arg::No,
+        hint: "",
+        help: "Scan volume for deleted inodes, and expose them read-only under .lost+found \
+            in the mount root.",
+        src: ANY,
+        linux: false,
+        set: |opt, v| {
+            opt.lost_found = v.get_flag()?;
+            Ok(())
+        },
+    },
+];
+
+// None if unknown to src on this OS
+pub(crate) fn find(name: &str, src: u8) -> Option<&'static Desc> {
+    OPTIONS
+        .iter()
+        .find(|x| x.name == name && x.src & src != 0 && (!x.linux || libfs::os::is_linux()))
+}
+
+pub(crate) fn is_linux_only(name: &str) -> bool {
+    OPTIONS.iter().any(|x| x.name == name && x.linux)
+}
+
+pub(crate) fn init_getopts(gopt: &mut getopts::Options) {
+    for x in OPTIONS
+        .iter()
+        .filter(|x| x.src & CMDLINE != 0 && (!x.linux || libfs::os::is_linux()))
+    {
+        match x.arg {
+            Arg::No => gopt.optflag("", x.name, x.help),
+            Arg::Required => gopt.optopt("", x.name, x.help, x.hint),
+            Arg::Optional => gopt.optflagopt("", x.name, x.help, x.hint),
+        };
+    }
+}
+
+impl Opt {
+    // command line options override configuration file
+    pub(crate) fn apply_config(&mut self, cfg: &crate::config::Section) -> nix::Result<()> {
+        for (k, v) in cfg {
+            let Some(x) = find(k, CONFIG) else {
+                if is_linux_only(k) {
+                    continue;
+                }
+                eprintln!("unknown option {k}");
+                return Err(nix::errno::Errno::EINVAL);
+            };
+            let s;
+            let v = match v {
+                toml::Value::Boolean(v) => Value::Flag(*v),
+                toml::Value::String(v) => Value::Str(v),
+                toml::Value::Integer(_) | toml::Value::Float(_) => {
+                    s = v.to_string();
+                    Value::Str(&s)
+                }
+                _ => {
+                    eprintln!("invalid option {k}");
+                    return Err(nix::errno::Errno::EINVAL);
+                }
+            };
+            if let Err(e) = (x.set)(self, v) {
+                eprintln!("invalid option {k}");
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn parse_args(&mut self, matches: &getopts::Matches) -> nix::Result<()> {
+        for x in OPTIONS
+            .iter()
+            .filter(|x| x.src & CMDLINE != 0 && (!x.linux || libfs::os::is_linux()))
+        {
+            if !matches.opt_present(x.name) {
+                continue;
+            }
+            let s = matches.opt_str(x.name);
+            let v = match &s {
+                Some(v) => Value::Str(v),
+                None => Value::Flag(true),
+            };
+            if let Err(e) = (x.set)(self, v) {
+                eprintln!("invalid option {}", x.name);
+                return Err(e);
+            }
+        }
+        for s in &matches.opt_strs("o") {
+            self.parse(s, matches.opt_present("s"))?;
+        }
+        Ok(())
+    }
+
+    fn parse(&mut self, s: &str, sloppy: bool) -> nix::Result<()> {
+        for x in s.split(',').filter(|x| !x.is_empty()) {
+            let (k, v) = match x.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (x, None),
+            };
+            if let Err(e) = self.parse_option(k, v) {
+                if sloppy && e == nix::errno::Errno::ENOTSUP {
+                    eprintln!("ignoring unknown option {x}");
+                    continue;
+                }
+                eprintln!("invalid option {x}");
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_option(&mut self, k: &str, v: Option<&str>) -> nix::Result<()> {
+        if let Some(x) = find(k, MOUNTOPT) {
+            return (x.set)(
+                self,
+                match v {
+                    Some(v) => Value::Str(v),
+                    None => Value::Flag(true),
+                },
+            );
+        }
+        match (k, v) {
+            // always read-only even with rw, which mount(8) passes unless ro
+            // is given, no device files and no setuid
+            ("rw" | "dev" | "nodev" | "suid" | "nosuid" | "subtype", _) => (),
+            (k, None) if IGNORED.contains(&k) => (),
+            (k, _) if k.starts_with("x-") || k == "comment" => (),
+            _ => return Err(nix::errno::Errno::ENOTSUP),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_spec(&self, spec: &str) -> nix::Result<String> {
+        match &self.label {
+            Some(v) => {
+                if spec.contains('@') {
+                    eprintln!("label specified twice");
+                    return Err(nix::errno::Errno::EINVAL);
+                }
+                Ok(format!("{spec}@{v}"))
+            }
+            None => Ok(spec.to_string()),
+        }
+    }
+}
+
+fn parse_uidmap(s: &str) -> nix::Result<crate::idmap::IdMap> {
+    crate::idmap::IdMap::new(s, nix::unistd::getuid().as_raw())
+}
+
+fn parse_gidmap(s: &str) -> nix::Result<crate::idmap::IdMap> {
+    crate::idmap::IdMap::new(s, nix::unistd::getgid().as_raw())
+}
+
+fn parse_log_format(s: &str) -> nix::Result<bool> {
+    match s {
+        "text" => Ok(false),
+        "json" => Ok(true),
+        _ => {
+            eprintln!("invalid log format {s}");
+            Err(nix::errno::Errno::EINVAL)
+        }
+    }
+}
+
+fn parse_size(s: &str) -> nix::Result<u64> {
+    s.parse().map_err(|_| {
+        eprintln!("invalid size {s}");
+        nix::errno::Errno::EINVAL
+    })
+}
+
+// whole window is read by a single request
+fn check_readahead(v: u64) -> u64 {
+    if v > crate::readahead::MAX_WINDOW {
+        eprintln!("readahead {v} capped to {}", crate::readahead::MAX_WINDOW);
+        crate::readahead::MAX_WINDOW
+    } else {
+        v
+    }
+}
+
+fn parse_count(s: &str) -> nix::Result<usize> {
+    s.parse().map_err(|_| {
+        eprintln!("invalid count {s}");
+        nix::errno::Errno::EINVAL
+    })
+}
+
+fn parse_psi(s: &str) -> nix::Result<f64> {
+    check_psi(s.parse().map_err(|_| nix::errno::Errno::EINVAL)?)
+}
+
+fn check_psi(v: f64) -> nix::Result<f64> {
+    if (0.0..=100.0).contains(&v) {
+        Ok(v)
+    } else {
+        eprintln!("invalid percentage {v}");
+        Err(nix::errno::Errno::EINVAL)
+    }
+}
+
+fn parse_timeout(s: &str) -> nix::Result<std::time::Duration> {
+    secs2duration(s.parse().map_err(|_| nix::errno::Errno::EINVAL)?)
+}
+
+fn parse_interval(s: &str) -> nix::Result<std::time::Duration> {
+    check_interval(parse_timeout(s)?)
+}
+
+fn check_interval(v: std::time::Duration) -> nix::Result<std::time::Duration> {
+    if v.is_zero() {
+        eprintln!("invalid interval {v:?}");
+        Err(nix::errno::Errno::EINVAL)
+    } else {
+        Ok(v)
+    }
+}
+
+fn secs2duration(secs: f64) -> nix::Result<std::time::Duration> {
+    std::time::Duration::try_from_secs_f64(secs).map_err(|_| nix::errno::Errno::EINVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse() {
+        let mut opt = super::Opt::default();
+        assert!(
+            opt.parse(
+                "allow_other,noauto,x-systemd.automount,label=ROOT,entry_timeout=2",
+                false
+            )
+            .is_ok()
+        );
+        assert!(opt.allow_other);
+        assert_eq!(opt.label.as_deref(), Some("ROOT"));
+        assert_eq!(opt.entry_timeout, Some(std::time::Duration::from_secs(2)));
+
+        let mut opt = super::Opt::default();
+        assert!(opt.parse("rw,nosuid,nodev", false).is_ok());
+        assert_eq!(opt.parse("unknown", false), Err(nix::errno::Errno::ENOTSUP));
+        assert!(opt.parse("unknown,noexec", true).is_ok());
+        assert!(opt.noexec);
+        assert_eq!(
+            opt.parse("attr_timeout=x", true),
+            Err(nix::errno::Errno::EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_get_spec() {
+        let mut opt = super::Opt::default();
+        assert_eq!(opt.get_spec("/dev/sdb1"), Ok("/dev/sdb1".to_string()));
+        opt.label = Some("DATA".to_string());
+        assert_eq!(opt.get_spec("/dev/sdb1"), Ok("/dev/sdb1@DATA".to_string()));
+        assert_eq!(
+            opt.get_spec("/dev/sdb1@ROOT"),
+            Err(nix::errno::Errno::EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(super::parse_timeout("0"), Ok(std::time::Duration::ZERO));
+        assert_eq!(
+            super::parse_timeout("1.5"),
+            Ok(std::time::Duration::from_millis(1500))
+        );
+        assert!(super::parse_timeout("-1").is_err());
+        assert!(super::parse_timeout("x").is_err());
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!(super::parse_log_format("text"), Ok(false));
+        assert_eq!(super::parse_log_format("json"), Ok(true));
+        assert!(super::parse_log_format("xml").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(super::parse_size("0"), Ok(0));
+        assert_eq!(super::parse_size("1048576"), Ok(1_048_576));
+        assert!(super::parse_size("").is_err());
+        assert!(super::parse_size("-1").is_err());
+        assert!(super::parse_size("1M").is_err());
+        let mut opt = super::Opt::default();
+        assert!(opt.parse("readahead=4096", false).is_ok());
+        assert_eq!(opt.readahead, Some(4096));
+        assert!(opt.parse("readahead=1073741824", false).is_ok());
+        assert_eq!(opt.readahead, Some(crate::readahead::MAX_WINDOW));
+        assert_eq!(
+            opt.parse("readahead=x", false),
+            Err(nix::errno::Errno::EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(super::parse_count("100"), Ok(100));
+        assert!(super::parse_count("1.5").is_err());
+        assert!(super::parse_count("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_psi() {
+        assert_eq!(super::parse_psi("0"), Ok(0.0));
+        assert_eq!(super::parse_psi("12.5"), Ok(12.5));
+        assert_eq!(super::parse_psi("100"), Ok(100.0));
+        assert!(super::parse_psi("100.1").is_err());
+        assert!(super::parse_psi("-1").is_err());
+        assert!(super::parse_psi("NaN").is_err());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert!(super::parse_interval("0").is_err());
+        assert_eq!(
+            super::parse_interval("5"),
+            Ok(std::time::Duration::from_secs(5))
+        );
+    }
+}
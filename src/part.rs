@@ -0,0 +1,469 @@
+// partition selector for whole disk images
+//
+// #sN   MBR slice N (1-4), or GPT partition N counting from 0 as DragonFly does
+// #sNX  disklabel64 partition X (a-p) in slice N
+// #pN   GPT or MBR partition N counting from 1
+// #auto first partition containing HAMMER2 volume header
+// offset=N[,size=M] byte range
+const SECTOR_SIZE: u64 = 512;
+const MBR_SIGNATURE: u16 = 0xAA55;
+const MBR_TYPE_GPT: u8 = 0xEE;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const DISKMAGIC64: u32 = 0xC446_4C59;
+const DISKLABEL64_NPARTITIONS: u64 = 16;
+const DISKLABEL64_PARTITIONS: u64 = 208;
+const PARTITION64_SIZE: u64 = 64;
+const GPT_MAX_ENTRIES: u32 = 1024;
+const GPT_MAX_ENTRY_SIZE: u32 = 512;
+
+fn invalid_input(s: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, s)
+}
+
+fn not_found(s: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, s)
+}
+
+fn read_buf(
+    src: &mut dyn crate::blksrc::BlockSource,
+    offset: u64,
+    size: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut b = vec![0; size];
+    if src.read_at(&mut b, offset)? != size {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+    }
+    Ok(b)
+}
+
+fn get_u32(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(b[i..i + 4].try_into().unwrap())
+}
+
+fn get_u64(b: &[u8], i: usize) -> u64 {
+    u64::from_le_bytes(b[i..i + 8].try_into().unwrap())
+}
+
+#[derive(Debug)]
+struct Part {
+    name: String, // selector
+    offset: u64,
+    size: u64,
+}
+
+// byte range of sectors, None if not within limit
+fn get_range(lba: u64, nsectors: u64, limit: u64) -> Option<(u64, u64)> {
+    let offset = lba.checked_mul(SECTOR_SIZE)?;
+    let size = nsectors.checked_mul(SECTOR_SIZE)?;
+    if offset.checked_add(size)? > limit {
+        return None;
+    }
+    Some((offset, size))
+}
+
+fn read_gpt(src: &mut dyn crate::blksrc::BlockSource) -> std::io::Result<Option<Vec<Part>>> {
+    let disk_size = src.get_size();
+    let b = read_buf(src, SECTOR_SIZE, usize::try_from(SECTOR_SIZE).unwrap())?;
+    if &b[..8] != GPT_SIGNATURE {
+        return Ok(None);
+    }
+    let lba = get_u64(&b, 72);
+    let n = get_u32(&b, 80);
+    let esize = get_u32(&b, 84);
+    if !(128..=GPT_MAX_ENTRY_SIZE).contains(&esize) || n > GPT_MAX_ENTRIES {
+        return Err(invalid_input("invalid GPT header"));
+    }
+    let offset = lba
+        .checked_mul(SECTOR_SIZE)
+        .ok_or_else(|| invalid_input("invalid GPT header"))?;
+    let size = usize::try_from(n * esize).map_err(|_| invalid_input("invalid GPT header"))?;
+    let t = read_buf(src, offset, size)?;
+    let mut v = vec![];
+    for (i, e) in t.chunks_exact(usize::try_from(esize).unwrap()).enumerate() {
+        if e[..16].iter().all(|&x| x == 0) {
+            continue; // unused
+        }
+        let (first, last) = (get_u64(e, 32), get_u64(e, 40));
+        let Some((offset, size)) = last
+            .checked_sub(first)
+            .and_then(|x| x.checked_add(1))
+            .and_then(|x| get_range(first, x, disk_size))
+        else {
+            log::warn!("GPT entry {i}: invalid LBA range {first}-{last}");
+            continue;
+        };
+        v.push(Part {
+            name: format!("p{}", i + 1),
+            offset,
+            size,
+        });
+        v.push(Part {
+            name: format!("s{i}"),
+            offset,
+            size,
+        });
+    }
+    Ok(Some(v))
+}
+
+fn read_mbr(src: &mut dyn crate::blksrc::BlockSource) -> std::io::Result<Option<Vec<Part>>> {
+    let disk_size = src.get_size();
+    let b = read_buf(src, 0, usize::try_from(SECTOR_SIZE).unwrap())?;
+    if u16::from_le_bytes([b[510], b[511]]) != MBR_SIGNATURE {
+        return Ok(None);
+    }
+    let mut v = vec![];
+    for i in 0..4 {
+        let e = &b[446 + 16 * i..446 + 16 * (i + 1)];
+        let typ = e[4];
+        let (lba, n) = (u64::from(get_u32(e, 8)), u64::from(get_u32(e, 12)));
+        if typ == 0 || n == 0 || MBR_TYPE_EXTENDED.contains(&typ) {
+            continue;
+        }
+        if typ == MBR_TYPE_GPT {
+            return Ok(None); // protective MBR
+        }
+        let Some((offset, size)) = get_range(lba, n, disk_size) else {
+            log::warn!("MBR slice {}: beyond end of disk", i + 1);
+            continue;
+        };
+        for name in [format!("s{}", i + 1), format!("p{}", i + 1)] {
+            v.push(Part { name, offset, size });
+        }
+    }
+    Ok(Some(v))
+}
+
+// partitions of disklabel64 at the beginning of a slice
+fn read_disklabel64(
+    src: &mut dyn crate::blksrc::BlockSource,
+    slice: &Part,
+) -> std::io::Result<Vec<Part>> {
+    let size = DISKLABEL64_PARTITIONS + DISKLABEL64_NPARTITIONS * PARTITION64_SIZE;
+    if slice.size < size {
+        return Ok(vec![]);
+    }
+    let b = read_buf(
+        src,
+        slice.offset,
+        usize::try_from(size).map_err(|_| invalid_input("invalid disklabel64"))?,
+    )?;
+    if get_u32(&b, 4) != DISKMAGIC64 {
+        return Ok(vec![]);
+    }
+    let n = std::cmp::min(u64::from(get_u32(&b, 16)), DISKLABEL64_NPARTITIONS);
+    let mut v = vec![];
+    for (i, c) in (0..n).zip(b'a'..) {
+        let Ok(j) = usize::try_from(DISKLABEL64_PARTITIONS + i * PARTITION64_SIZE) else {
+            continue;
+        };
+        let (boffset, bsize) = (get_u64(&b, j), get_u64(&b, j + 8));
+        if bsize == 0 || boffset.checked_add(bsize).is_none_or(|x| x > slice.size) {
+            continue;
+        }
+        v.push(Part {
+            name: format!("{}{}", slice.name, char::from(c)),
+            offset: slice.offset + boffset,
+            size: bsize,
+        });
+    }
+    Ok(v)
+}
+
+fn get_parts(src: &mut dyn crate::blksrc::BlockSource) -> std::io::Result<Vec<Part>> {
+    let mut v = match read_gpt(src)? {
+        Some(v) => v,
+        None => read_mbr(src)?.unwrap_or_default(),
+    };
+    let mut l = vec![];
+    for x in v.iter().filter(|x| x.name.starts_with('s')) {
+        l.extend(read_disklabel64(src, x)?);
+    }
+    v.extend(l);
+    for x in &v {
+        log::debug!("{x:?}");
+    }
+    Ok(v)
+}
+
+pub(crate) fn is_hammer2(
+    src: &mut dyn crate::blksrc::BlockSource,
+    offset: u64,
+) -> std::io::Result<bool> {
+    let mut b = [0; 8];
+    if src.read_at(&mut b, offset)? != b.len() {
+        return Ok(false);
+    }
+    let magic = u64::from_le_bytes(b);
+    Ok(magic == libhammer2::fs::HAMMER2_VOLUME_ID_HBO
+        || magic == libhammer2::fs::HAMMER2_VOLUME_ID_ABO)
+}
+
+fn parse_range(s: &str, disk_size: u64) -> std::io::Result<(u64, u64)> {
+    let mut offset: Option<u64> = None;
+    let mut size = None;
+    for x in s.split(',') {
+        let (k, v) = x
+            .split_once('=')
+            .ok_or_else(|| invalid_input("invalid range"))?;
+        let v = v.parse().map_err(|_| invalid_input("invalid range"))?;
+        match k {
+            "offset" => offset = Some(v),
+            "size" => size = Some(v),
+            _ => return Err(invalid_input("invalid range")),
+        }
+    }
+    let offset = offset.ok_or_else(|| invalid_input("offset not specified"))?;
+    if offset >= disk_size {
+        return Err(invalid_input("offset beyond end of disk"));
+    }
+    let size = size.unwrap_or(disk_size - offset);
+    if offset.checked_add(size).is_none_or(|x| x > disk_size) {
+        return Err(invalid_input("range beyond end of disk"));
+    }
+    Ok((offset, size))
+}
+
+// returns byte range of selected partition
+pub(crate) fn find(
+    src: &mut dyn crate::blksrc::BlockSource,
+    sel: &str,
+) -> std::io::Result<(u64, u64)> {
+    let disk_size = src.get_size();
+    let (offset, size) = if sel.starts_with("offset=") {
+        parse_range(sel, disk_size)?
+    } else if sel == "auto" {
+        let mut v = get_parts(src)?;
+        v.retain(|x| !x.name.starts_with('p')); // aliases
+        v.push(Part {
+            name: String::new(),
+            offset: 0,
+            size: disk_size,
+        });
+        let mut found = None;
+        for x in &v {
+            if is_hammer2(src, x.offset)? {
+                found = Some(x);
+                break;
+            }
+        }
+        let x = found.ok_or_else(|| not_found("no HAMMER2 partition found"))?;
+        log::info!("found HAMMER2 at {x:?}");
+        (x.offset, x.size)
+    } else {
+        let v = get_parts(src)?;
+        let x = v
+            .iter()
+            .find(|x| x.name == sel)
+            .ok_or_else(|| not_found(&format!("partition {sel} not found")))?;
+        (x.offset, x.size)
+    };
+    if offset.checked_add(size).is_none_or(|x| x > disk_size) {
+        return Err(invalid_input("partition beyond end of disk"));
+    }
+    if !is_hammer2(src, offset)? {
+        log::warn!("no HAMMER2 volume header at offset {offset}");
+    }
+    Ok((offset, size))
+}
+
+#[cfg(test)]
+mod tests {
+    const SECTOR: usize = 512;
+
+    struct MemSource(Vec<u8>);
+
+    impl crate::blksrc::BlockSource for MemSource {
+        fn get_size(&self) -> u64 {
+            u64::try_from(self.0.len()).unwrap()
+        }
+
+        fn read_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+            let b = self
+                .0
+                .get(usize::try_from(offset).unwrap()..)
+                .unwrap_or(&[]);
+            let n = std::cmp::min(buf.len(), b.len());
+            buf[..n].copy_from_slice(&b[..n]);
+            Ok(n)
+        }
+    }
+
+    fn put_u32(b: &mut [u8], i: usize, v: u32) {
+        b[i..i + 4].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_u64(b: &mut [u8], i: usize, v: u64) {
+        b[i..i + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    fn put_hammer2(b: &mut [u8], lba: usize) {
+        put_u64(b, lba * SECTOR, libhammer2::fs::HAMMER2_VOLUME_ID_HBO);
+    }
+
+    fn put_mbr(b: &mut [u8], i: usize, typ: u8, lba: u32, n: u32) {
+        let e = 446 + 16 * i;
+        b[e + 4] = typ;
+        put_u32(b, e + 8, lba);
+        put_u32(b, e + 12, n);
+        b[510..512].copy_from_slice(&super::MBR_SIGNATURE.to_le_bytes());
+    }
+
+    // GPT entries at LBA 2, 128 bytes each
+    fn put_gpt(b: &mut [u8], l: &[(u64, u64)]) {
+        put_mbr(b, 0, super::MBR_TYPE_GPT, 1, 0xFFFF_FFFF);
+        b[SECTOR..SECTOR + 8].copy_from_slice(super::GPT_SIGNATURE);
+        put_u64(b, SECTOR + 72, 2);
+        put_u32(b, SECTOR + 80, u32::try_from(l.len()).unwrap());
+        put_u32(b, SECTOR + 84, 128);
+        for (i, &(first, last)) in l.iter().enumerate() {
+            let e = 2 * SECTOR + 128 * i;
+            if first != 0 {
+                b[e] = 1; // type guid
+            }
+            put_u64(b, e + 32, first);
+            put_u64(b, e + 40, last);
+        }
+    }
+
+    fn get_names(v: &[super::Part]) -> Vec<(&str, u64, u64)> {
+        v.iter()
+            .map(|x| (x.name.as_str(), x.offset, x.size))
+            .collect()
+    }
+
+    #[test]
+    fn test_gpt() {
+        let mut b = vec![0; 128 * SECTOR];
+        put_gpt(
+            &mut b,
+            &[(34, 63), (0, 0), (64, 127), (100, 1000), (50, 40)],
+        );
+        put_hammer2(&mut b, 64);
+        let mut src = MemSource(b);
+        let v = super::read_gpt(&mut src).unwrap().unwrap();
+        assert_eq!(
+            get_names(&v),
+            [
+                ("p1", 34 * 512, 30 * 512),
+                ("s0", 34 * 512, 30 * 512),
+                ("p3", 64 * 512, 64 * 512),
+                ("s2", 64 * 512, 64 * 512),
+            ]
+        );
+        // protective MBR
+        assert!(super::read_mbr(&mut src).unwrap().is_none());
+        assert_eq!(super::find(&mut src, "s2").unwrap(), (64 * 512, 64 * 512));
+        assert_eq!(super::find(&mut src, "p3").unwrap(), (64 * 512, 64 * 512));
+        assert_eq!(super::find(&mut src, "auto").unwrap(), (64 * 512, 64 * 512));
+        assert!(super::find(&mut src, "p4").is_err());
+    }
+
+    #[test]
+    fn test_gpt_invalid() {
+        let mut b = vec![0; 128 * SECTOR];
+        put_gpt(&mut b, &[(34, 63)]);
+        put_u32(&mut b, SECTOR + 84, 64);
+        assert!(super::read_gpt(&mut MemSource(b.clone())).is_err());
+        put_u32(&mut b, SECTOR + 84, 128);
+        put_u32(&mut b, SECTOR + 80, super::GPT_MAX_ENTRIES + 1);
+        assert!(super::read_gpt(&mut MemSource(b.clone())).is_err());
+        put_u32(&mut b, SECTOR + 80, 1);
+        put_u64(&mut b, SECTOR + 72, u64::MAX);
+        assert!(super::read_gpt(&mut MemSource(b)).is_err());
+        assert!(
+            super::read_gpt(&mut MemSource(vec![0; 128 * SECTOR]))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_mbr() {
+        let mut b = vec![0; 128 * SECTOR];
+        put_mbr(&mut b, 0, 0x83, 16, 16);
+        put_mbr(&mut b, 1, 0x05, 32, 16); // extended
+        put_mbr(&mut b, 2, 0xA5, 64, 64);
+        put_mbr(&mut b, 3, 0x83, 100, 100); // beyond end
+        put_hammer2(&mut b, 64);
+        let mut src = MemSource(b);
+        let v = super::read_mbr(&mut src).unwrap().unwrap();
+        assert_eq!(
+            get_names(&v),
+            [
+                ("s1", 16 * 512, 16 * 512),
+                ("p1", 16 * 512, 16 * 512),
+                ("s3", 64 * 512, 64 * 512),
+                ("p3", 64 * 512, 64 * 512),
+            ]
+        );
+        assert_eq!(super::find(&mut src, "auto").unwrap(), (64 * 512, 64 * 512));
+        assert!(super::find(&mut src, "s4").is_err());
+        assert!(
+            super::read_mbr(&mut MemSource(vec![0; SECTOR]))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_disklabel64() {
+        let mut b = vec![0; 256 * SECTOR];
+        put_mbr(&mut b, 0, 0xA5, 32, 224);
+        let j = 32 * SECTOR;
+        put_u32(&mut b, j + 4, super::DISKMAGIC64);
+        put_u32(&mut b, j + 16, 4);
+        let p = j + usize::try_from(super::DISKLABEL64_PARTITIONS).unwrap();
+        put_u64(&mut b, p, 4096); // a
+        put_u64(&mut b, p + 8, 8192);
+        put_u64(&mut b, p + 128, 16384); // c, d unused
+        put_u64(&mut b, p + 128 + 8, 1 << 40);
+        put_u64(&mut b, p + 192, u64::MAX);
+        put_u64(&mut b, p + 192 + 8, 2);
+        put_hammer2(&mut b, 40);
+        let mut src = MemSource(b);
+        let slice = super::Part {
+            name: "s1".to_string(),
+            offset: 32 * 512,
+            size: 224 * 512,
+        };
+        let v = super::read_disklabel64(&mut src, &slice).unwrap();
+        assert_eq!(get_names(&v), [("s1a", 32 * 512 + 4096, 8192)]);
+        assert_eq!(
+            super::find(&mut src, "s1a").unwrap(),
+            (32 * 512 + 4096, 8192)
+        );
+        assert_eq!(
+            super::find(&mut src, "auto").unwrap(),
+            (32 * 512 + 4096, 8192)
+        );
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            super::parse_range("offset=1024", 4096).unwrap(),
+            (1024, 3072)
+        );
+        assert_eq!(
+            super::parse_range("offset=1024,size=1024", 4096).unwrap(),
+            (1024, 1024)
+        );
+        assert_eq!(
+            super::parse_range("size=1024,offset=0", 4096).unwrap(),
+            (0, 1024)
+        );
+        for s in [
+            "size=1024",
+            "offset=4096",
+            "offset=1024,size=4096",
+            "offset=1,size=18446744073709551615",
+            "offset=x",
+            "offset=0,foo=1",
+            "offset",
+        ] {
+            assert!(super::parse_range(s, 4096).is_err(), "{s}");
+        }
+    }
+}
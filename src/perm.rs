@@ -0,0 +1,163 @@
+// permission checks done by the filesystem without default_permissions
+
+// fuser::Request has no supplementary groups
+fn get_groups(pid: u32) -> Vec<u32> {
+    if !libfs::os::is_linux() {
+        return vec![];
+    }
+    let Ok(buf) = std::fs::read_to_string(format!("/proc/{pid}/status")) else {
+        return vec![];
+    };
+    for l in buf.lines() {
+        if let Some(v) = l.strip_prefix("Groups:") {
+            return v
+                .split_whitespace()
+                .filter_map(|x| x.parse().ok())
+                .collect();
+        }
+    }
+    vec![]
+}
+
+fn check_mode(attr: &fuser::FileAttr, uid: u32, gid: u32, groups: &[u32], mask: i32) -> bool {
+    let perm = u32::from(attr.perm);
+    let mut need = 0;
+    if mask & libc::R_OK != 0 {
+        need |= 4;
+    }
+    if mask & libc::W_OK != 0 {
+        need |= 2;
+    }
+    if mask & libc::X_OK != 0 {
+        need |= 1;
+    }
+    if uid == 0 {
+        // root needs at least one execute bit unless directory
+        return need & 1 == 0 || attr.kind == fuser::FileType::Directory || perm & 0o111 != 0;
+    }
+    let bits = if uid == attr.uid {
+        perm >> 6
+    } else if gid == attr.gid || groups.contains(&attr.gid) {
+        perm >> 3
+    } else {
+        perm
+    };
+    bits & need == need
+}
+
+impl crate::Hammer2Fuse {
+    pub(crate) fn check_access(
+        &mut self,
+        req: &fuser::Request<'_>,
+        inum: u64,
+        mask: i32,
+    ) -> libhammer2::Result<()> {
+        let (attr, uflags) = if crate::lostfound::is_virtual(inum) {
+            (self.get_lost_found_attr(inum)?, 0)
+        } else {
+            let st = self.pmp.stat(inum)?;
+            let meta = self.pmp.get_inode(inum).map(|ip| ip.get_meta());
+            let uflags = meta.map_or(0, |v| v.uflags);
+            (crate::util::stat2attr(&st, meta, &self.idmap)?, uflags)
+        };
+        if mask & libc::W_OK != 0 {
+            if crate::util::is_immutable(uflags) || crate::util::is_append(uflags) {
+                return Err(nix::errno::Errno::EPERM.into());
+            }
+            return Err(nix::errno::Errno::EROFS.into());
+        }
+        let groups = if req.gid() == attr.gid {
+            vec![]
+        } else {
+            get_groups(req.pid())
+        };
+        if check_mode(&attr, req.uid(), req.gid(), &groups, mask) {
+            Ok(())
+        } else {
+            Err(nix::errno::Errno::EACCES.into())
+        }
+    }
+}
+
+pub(crate) fn flags2mask(flags: i32) -> i32 {
+    let mut mask = match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => libc::W_OK,
+        libc::O_RDWR => libc::R_OK | libc::W_OK,
+        _ => libc::R_OK,
+    };
+    if flags & libc::O_TRUNC != 0 {
+        mask |= libc::W_OK;
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    fn get_attr(kind: fuser::FileType, perm: u16) -> fuser::FileAttr {
+        fuser::FileAttr {
+            ino: 2,
+            size: 0,
+            blocks: 0,
+            atime: std::time::UNIX_EPOCH,
+            mtime: std::time::UNIX_EPOCH,
+            ctime: std::time::UNIX_EPOCH,
+            crtime: std::time::UNIX_EPOCH,
+            kind,
+            perm,
+            nlink: 1,
+            uid: 1000,
+            gid: 100,
+            rdev: 0,
+            blksize: 512,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn test_check_mode() {
+        let attr = get_attr(fuser::FileType::RegularFile, 0o640);
+        let (r, w, x) = (libc::R_OK, libc::W_OK, libc::X_OK);
+        // owner
+        assert!(super::check_mode(&attr, 1000, 1000, &[], r));
+        assert!(super::check_mode(&attr, 1000, 1000, &[], r | w));
+        assert!(!super::check_mode(&attr, 1000, 1000, &[], x));
+        // group, primary or supplementary
+        assert!(super::check_mode(&attr, 1001, 100, &[], r));
+        assert!(!super::check_mode(&attr, 1001, 100, &[], w));
+        assert!(super::check_mode(&attr, 1001, 1001, &[10, 100], r));
+        // other
+        assert!(!super::check_mode(&attr, 1001, 1001, &[10], r));
+        assert!(super::check_mode(&attr, 1001, 1001, &[], libc::F_OK));
+        // owner bits apply even if group or other allow more
+        let attr = get_attr(fuser::FileType::RegularFile, 0o077);
+        assert!(!super::check_mode(&attr, 1000, 100, &[], r));
+        assert!(super::check_mode(&attr, 1001, 1001, &[], r | w | x));
+    }
+
+    #[test]
+    fn test_check_mode_root() {
+        let (r, w, x) = (libc::R_OK, libc::W_OK, libc::X_OK);
+        let attr = get_attr(fuser::FileType::RegularFile, 0o600);
+        assert!(super::check_mode(&attr, 0, 0, &[], r | w));
+        assert!(!super::check_mode(&attr, 0, 0, &[], x));
+        let attr = get_attr(fuser::FileType::RegularFile, 0o001);
+        assert!(super::check_mode(&attr, 0, 0, &[], r | x));
+        let attr = get_attr(fuser::FileType::Directory, 0o000);
+        assert!(super::check_mode(&attr, 0, 0, &[], r | x));
+    }
+
+    #[test]
+    fn test_flags2mask() {
+        assert_eq!(super::flags2mask(libc::O_RDONLY), libc::R_OK);
+        assert_eq!(super::flags2mask(libc::O_WRONLY), libc::W_OK);
+        assert_eq!(super::flags2mask(libc::O_RDWR), libc::R_OK | libc::W_OK);
+        assert_eq!(
+            super::flags2mask(libc::O_RDONLY | libc::O_TRUNC),
+            libc::R_OK | libc::W_OK
+        );
+        assert_eq!(
+            super::flags2mask(libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_CLOEXEC),
+            libc::R_OK
+        );
+    }
+}
@@ -0,0 +1,65 @@
+// "special@clid=<uuid>" and "special@fsid=<uuid>" resolved to PFS name
+#[derive(Debug)]
+struct Pfs {
+    name: String,
+    clid: String,
+    fsid: String,
+}
+
+pub(crate) fn get_pfs_list(
+    pmp: &mut libhammer2::hammer2::Hammer2,
+) -> libhammer2::Result<Vec<libhammer2::ioctl::IocPfs>> {
+    let mut v = vec![];
+    let mut ioc = libhammer2::ioctl::IocPfs::new();
+    ioc.name_key = 0;
+    loop {
+        ioc = crate::ioctl::pfs_get(pmp, &ioc)?;
+        v.push(ioc);
+        if ioc.name_next == u64::MAX {
+            break;
+        }
+        ioc.name_key = ioc.name_next;
+    }
+    Ok(v)
+}
+
+// returns spec with label if selected by uuid
+pub(crate) fn resolve_spec(spec: &str, mopt: &[&str]) -> crate::Result<Option<String>> {
+    let Some((special, label)) = spec.split_once('@') else {
+        return Ok(None);
+    };
+    let (k, uuid) = match label.split_once('=') {
+        Some((k @ ("clid" | "fsid"), v)) => (k, v),
+        _ => return Ok(None),
+    };
+    // mount default PFS to walk super-root
+    let mut pmp = libhammer2::mount(special, mopt)?;
+    let l = get_pfs_list(&mut pmp);
+    pmp.unmount()?;
+    let mut v = vec![];
+    for x in l? {
+        v.push(Pfs {
+            name: String::from_utf8_lossy(x.get_name()?).into_owned(),
+            clid: x.pfs_clid.to_string(),
+            fsid: x.pfs_fsid.to_string(),
+        });
+    }
+    let l = v;
+    for x in &l {
+        log::debug!("{x:?}");
+    }
+    let Some(x) = l.iter().find(|x| {
+        let s = if k == "clid" { &x.clid } else { &x.fsid };
+        s.eq_ignore_ascii_case(uuid)
+    }) else {
+        log::error!("{k} {uuid} not found");
+        return Err(Box::new(nix::errno::Errno::ENOENT));
+    };
+    // label is the only way to select PFS in libhammer2
+    if l.iter().filter(|y| y.name == x.name).count() > 1 {
+        log::error!("{k} {uuid}: PFS name {} not unique", x.name);
+        return Err(Box::new(nix::errno::Errno::EEXIST));
+    }
+    log::info!("{k} {uuid}: {}", x.name);
+    Ok(Some(format!("{special}@{}", x.name)))
+}
@@ -0,0 +1,209 @@
+// identify HAMMER2 volumes without mounting, by reading volume headers
+
+// DragonFly struct uuid in little endian
+fn uuid2str(b: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{}",
+        u32::from_le_bytes(b[0..4].try_into().unwrap()),
+        u16::from_le_bytes(b[4..6].try_into().unwrap()),
+        u16::from_le_bytes(b[6..8].try_into().unwrap()),
+        b[8],
+        b[9],
+        b[10..16]
+            .iter()
+            .map(|x| format!("{x:02x}"))
+            .collect::<String>()
+    )
+}
+
+fn pfs_type2str(t: u8) -> &'static str {
+    match t {
+        0x00 => "NONE",
+        0x01 => "CACHE",
+        0x03 => "SLAVE",
+        0x04 => "SOFT_SLAVE",
+        0x05 => "SOFT_MASTER",
+        0x06 => "MASTER",
+        0x0F => "SUPROOT",
+        0x10 => "DUMMY",
+        _ => "UNKNOWN",
+    }
+}
+
+fn pfs_subtype2str(t: u8) -> &'static str {
+    match t {
+        0x00 => "NONE",
+        0x01 => "SNAPSHOT",
+        0x02 => "AUTOSNAP",
+        _ => "UNKNOWN",
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Header {
+    pub(crate) version: u32,
+    pub(crate) volu_id: u8,
+    pub(crate) nvolumes: u8,
+    pub(crate) volu_size: u64,
+    pub(crate) fsid: String,
+    pub(crate) mirror_tid: u64,
+}
+
+// None if header copy has no magic
+pub(crate) fn read_header(
+    src: &mut dyn crate::blksrc::BlockSource,
+    i: usize,
+) -> std::io::Result<Option<Header>> {
+    let n = std::mem::size_of::<libhammer2::fs::Hammer2VolumeData>();
+    let Some(offset) = u64::try_from(i)
+        .ok()
+        .and_then(|x| x.checked_mul(libhammer2::fs::HAMMER2_ZONE_BYTES64))
+    else {
+        return Ok(None);
+    };
+    let end = u64::try_from(n).map_or(u64::MAX, |x| offset.saturating_add(x));
+    let mut b = vec![0; n];
+    if end > src.get_size() || src.read_at(&mut b, offset)? != b.len() {
+        return Ok(None);
+    }
+    let voldata: &libhammer2::fs::Hammer2VolumeData = libfs::cast::align_to(&b);
+    if voldata.magic != libhammer2::fs::HAMMER2_VOLUME_ID_HBO {
+        if voldata.magic == libhammer2::fs::HAMMER2_VOLUME_ID_ABO {
+            log::warn!("header {i}: reverse endian not supported");
+        }
+        return Ok(None);
+    }
+    Ok(Some(Header {
+        version: voldata.version,
+        volu_id: voldata.volu_id,
+        nvolumes: voldata.nvolumes,
+        volu_size: voldata.volu_size,
+        fsid: uuid2str(voldata.fsid.as_bytes()),
+        mirror_tid: voldata.mirror_tid,
+    }))
+}
+
+// all header copies and index of the one with highest mirror_tid
+pub(crate) fn read_headers(
+    src: &mut dyn crate::blksrc::BlockSource,
+) -> std::io::Result<(Vec<Option<Header>>, Option<usize>)> {
+    let mut v = vec![];
+    for i in 0..libhammer2::fs::HAMMER2_NUM_VOLHDRS {
+        v.push(read_header(src, i)?);
+    }
+    let best = v
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| x.as_ref().map(|x| (i, x.mirror_tid)))
+        .max_by_key(|x| x.1)
+        .map(|x| x.0);
+    Ok((v, best))
+}
+
+fn get_pfs_list(
+    special: &str,
+    selector: Option<&str>,
+) -> crate::Result<Vec<libhammer2::ioctl::IocPfs>> {
+    let (helper, path) = crate::blkfuse::mount_special(special, selector)?;
+    let mut pmp = libhammer2::mount(&path, &[])?;
+    let v = crate::pfs::get_pfs_list(&mut pmp);
+    pmp.unmount()?;
+    drop(helper);
+    Ok(v?)
+}
+
+fn probe(spec: &str) -> crate::Result<serde_json::Value> {
+    let (special, selector, _) = crate::blksrc::parse_spec(spec);
+    let mut src = crate::blksrc::open_raw(special, selector)?;
+    let (hdrs, best) = read_headers(&mut *src)?;
+    let Some((best, h)) = best.and_then(|i| Some((i, hdrs.get(i)?.as_ref()?))) else {
+        return Err(Box::new(nix::errno::Errno::EINVAL));
+    };
+    // PFS list requires all volumes
+    let pfs = if h.nvolumes <= 1 {
+        match get_pfs_list(special, selector) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                log::error!("{spec}: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    Ok(serde_json::json!({
+        "path": spec,
+        "version": h.version,
+        "fsid": h.fsid,
+        "volu_id": h.volu_id,
+        "nvolumes": h.nvolumes,
+        "volu_size": h.volu_size,
+        "size": src.get_size(),
+        "header": best,
+        "headers": hdrs.iter().map(|x| x.as_ref().map(|x| x.mirror_tid)).collect::<Vec<_>>(),
+        "pfs": pfs.map(|v| v.iter().map(|x| serde_json::json!({
+            "name": String::from_utf8_lossy(x.get_name().unwrap_or_default()),
+            "type": pfs_type2str(x.pfs_type),
+            "subtype": pfs_subtype2str(x.pfs_subtype),
+            "clid": x.pfs_clid.to_string(),
+            "fsid": x.pfs_fsid.to_string(),
+        })).collect::<Vec<_>>()),
+    }))
+}
+
+fn print_text(v: &serde_json::Value) {
+    println!("{}", v["path"].as_str().unwrap_or_default());
+    for k in [
+        "version",
+        "fsid",
+        "volu_id",
+        "nvolumes",
+        "volu_size",
+        "header",
+    ] {
+        println!("    {k} {}", v[k]);
+    }
+    if let Some(l) = v["headers"].as_array() {
+        for (i, x) in l.iter().enumerate() {
+            match x.as_u64() {
+                Some(t) => println!("    header {i} mirror_tid 0x{t:016x}"),
+                None => println!("    header {i} invalid"),
+            }
+        }
+    }
+    if let Some(l) = v["pfs"].as_array() {
+        for x in l {
+            println!(
+                "    PFS {} type {} subtype {} clid {} fsid {}",
+                x["name"].as_str().unwrap_or_default(),
+                x["type"].as_str().unwrap_or_default(),
+                x["subtype"].as_str().unwrap_or_default(),
+                x["clid"].as_str().unwrap_or_default(),
+                x["fsid"].as_str().unwrap_or_default()
+            );
+        }
+    }
+}
+
+// fails if any is not HAMMER2
+pub(crate) fn run(args: &[String], json: bool) -> crate::Result<()> {
+    let mut l = vec![];
+    let mut ret = Ok(());
+    for x in args {
+        match probe(x) {
+            Ok(v) => l.push(v),
+            Err(e) => {
+                eprintln!("{x}: not HAMMER2 ({e})");
+                ret = Err(e);
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::Value::Array(l));
+    } else {
+        for v in &l {
+            print_text(v);
+        }
+    }
+    ret
+}
@@ -0,0 +1,165 @@
+// automatic chain pruning under memory pressure
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+pub(crate) struct Monitor {
+    rss: Option<u64>,      // RSS ceiling in bytes
+    psi: Option<f64>,      // /proc/pressure/memory some avg10 threshold
+    inodes: Option<usize>, // inodes looked up since last prune
+    cgroup: Option<String>,
+    nlookup: usize,
+    nevents: u64,
+    last: Option<std::time::Instant>,
+    pending: bool,
+}
+
+impl Monitor {
+    pub(crate) fn new(opt: &crate::option::Opt) -> Self {
+        let mut m = Self {
+            rss: opt.prune_rss.filter(|&v| v > 0),
+            psi: opt.prune_psi,
+            inodes: opt.prune_inodes.filter(|&v| v > 0),
+            ..Default::default()
+        };
+        if opt.prune_cgroup {
+            m.cgroup = get_cgroup_events_path();
+            if let Some(f) = &m.cgroup {
+                m.nevents = read_cgroup_events(f).unwrap_or(0);
+            }
+        }
+        m
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.rss.is_some() || self.psi.is_some() || self.inodes.is_some() || self.cgroup.is_some()
+    }
+
+    pub(crate) fn add_lookup(&mut self) {
+        self.nlookup += 1;
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    // returns reason if pruning is needed, checked at most once per interval
+    // except for inode count
+    pub(crate) fn check(&mut self) -> Option<String> {
+        if let Some(n) = self.inodes
+            && self.nlookup >= n
+        {
+            return Some(format!("{} inodes looked up", self.nlookup));
+        }
+        let now = std::time::Instant::now();
+        if let Some(t) = self.last
+            && now.duration_since(t) < CHECK_INTERVAL
+        {
+            return None;
+        }
+        self.last = Some(now);
+        if let Some(n) = self.rss
+            && let Some(v) = get_rss()
+            && v >= n
+        {
+            return Some(format!("RSS {v} bytes"));
+        }
+        if let Some(n) = self.psi
+            && let Some(v) = get_psi()
+            && v >= n
+        {
+            return Some(format!("memory pressure {v}"));
+        }
+        if let Some(f) = &self.cgroup
+            && let Some(v) = read_cgroup_events(f)
+            && v != self.nevents
+        {
+            self.nevents = v;
+            return Some(format!("cgroup memory events {v}"));
+        }
+        None
+    }
+
+    pub(crate) fn set_pending(&mut self) {
+        self.pending = true;
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.nlookup = 0;
+        self.pending = false;
+    }
+}
+
+fn get_rss() -> Option<u64> {
+    let s = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = s.split_whitespace().nth(1)?.parse().ok()?;
+    let pagesize = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+    Some(pages * pagesize)
+}
+
+// "some avg10=0.00 avg60=0.00 avg300=0.00 total=0"
+fn get_psi() -> Option<f64> {
+    let s = std::fs::read_to_string("/proc/pressure/memory").ok()?;
+    let l = s.lines().find(|l| l.starts_with("some "))?;
+    l.split_whitespace()
+        .find_map(|x| x.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+// cgroup v2 "0::/path"
+fn get_cgroup_events_path() -> Option<String> {
+    let s = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let d = s.lines().find_map(|l| l.strip_prefix("0::"))?;
+    let f = format!("/sys/fs/cgroup{}/memory.events", d.trim_end_matches('/'));
+    if std::path::Path::new(&f).is_file() {
+        Some(f)
+    } else {
+        log::warn!("{f} not available");
+        None
+    }
+}
+
+// sum of "high" and "max" events
+fn read_cgroup_events(f: &str) -> Option<u64> {
+    let s = std::fs::read_to_string(f).ok()?;
+    let mut n = 0;
+    for l in s.lines() {
+        if let Some((k, v)) = l.split_once(' ')
+            && (k == "high" || k == "max")
+        {
+            n += v.parse::<u64>().ok()?;
+        }
+    }
+    Some(n)
+}
+
+impl crate::Hammer2Fuse {
+    // prune_chain drops chains regardless of open inodes (see ioctl_cidprune),
+    // so references held by kernel lookup and open files are taken again
+    pub(crate) fn try_prune(&mut self) {
+        if !self.prune.is_enabled() {
+            return;
+        }
+        if !self.prune.is_pending() {
+            match self.prune.check() {
+                Some(v) => {
+                    log::info!("prune requested by {v}");
+                    self.prune.set_pending();
+                }
+                None => return,
+            }
+        }
+        match self.pmp.prune_chain() {
+            Ok(t) => {
+                log::info!("pruned chains, vchain {} fchain {}", t.0, t.1);
+                let l = self.get_ref_inodes();
+                if let Err(e) = crate::handle::get_refs(&mut self.pmp, &l) {
+                    log::error!("{e}");
+                }
+                self.invalidate_inodes();
+            }
+            Err(e) => log::error!("{e}"),
+        }
+        self.prune.reset();
+    }
+}
@@ -0,0 +1,175 @@
+// sequential read detection and read-ahead per file handle,
+// the window is read synchronously by the request that grows it
+pub(crate) const DEFAULT_WINDOW: u64 = 1024 * 1024;
+pub(crate) const MAX_WINDOW: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub(crate) struct ReadAhead {
+    next: u64,
+    window: u64,
+    offset: u64,
+    buf: Vec<u8>,
+}
+
+impl ReadAhead {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn get_cached(&self, offset: u64, size: u64) -> Option<&[u8]> {
+        if offset < self.offset {
+            return None;
+        }
+        let beg = usize::try_from(offset - self.offset).ok()?;
+        let end = usize::try_from((offset - self.offset).checked_add(size)?).ok()?;
+        if end <= self.buf.len() {
+            Some(&self.buf[beg..end])
+        } else {
+            None
+        }
+    }
+
+    // f reads size bytes at offset, returning less at EOF
+    pub(crate) fn read<F>(
+        &mut self,
+        offset: u64,
+        size: u64,
+        max_window: u64,
+        mut f: F,
+    ) -> libhammer2::Result<Vec<u8>>
+    where
+        F: FnMut(u64, u64) -> libhammer2::Result<Vec<u8>>,
+    {
+        let sequential = offset == self.next;
+        self.next = offset.saturating_add(size);
+        if let Some(v) = self.get_cached(offset, size) {
+            return Ok(v.to_vec());
+        }
+        if sequential && offset != 0 {
+            self.window = std::cmp::min(
+                std::cmp::max(self.window.saturating_mul(2), size.saturating_mul(2)),
+                max_window,
+            );
+        } else {
+            self.window = 0;
+        }
+        if self.window <= size {
+            self.buf.clear();
+            return f(size, offset);
+        }
+        log::debug!("offset {offset} window {}", self.window);
+        self.buf = f(self.window, offset)?;
+        self.offset = offset;
+        let n = std::cmp::min(size, self.buf.len().try_into().unwrap_or(u64::MAX));
+        Ok(self.get_cached(offset, n).unwrap_or_default().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // file of given size with byte value offset % 251, records read sizes
+    fn reader(
+        fsize: u64,
+        reads: &mut Vec<(u64, u64)>,
+    ) -> impl FnMut(u64, u64) -> libhammer2::Result<Vec<u8>> + '_ {
+        move |size, offset| {
+            reads.push((size, offset));
+            let end = std::cmp::min(offset.saturating_add(size), fsize);
+            Ok((offset..end)
+                .map(|x| u8::try_from(x % 251).unwrap())
+                .collect())
+        }
+    }
+
+    fn expected(size: u64, offset: u64) -> Vec<u8> {
+        (offset..offset + size)
+            .map(|x| u8::try_from(x % 251).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sequential() {
+        let mut ra = super::ReadAhead::new();
+        let mut reads = vec![];
+        let mut offset = 0;
+        for _ in 0..8 {
+            let v = ra
+                .read(offset, 4096, 65536, reader(1 << 20, &mut reads))
+                .unwrap();
+            assert_eq!(v, expected(4096, offset));
+            offset += 4096;
+        }
+        // first read not read ahead, then window doubles
+        assert_eq!(reads[0], (4096, 0), "{reads:?}");
+        assert_eq!(reads[1], (8192, 4096));
+        assert_eq!(reads[2], (16384, 12288));
+        assert_eq!(reads[3], (32768, 28672));
+        assert_eq!(reads.len(), 4);
+    }
+
+    #[test]
+    fn test_max_window() {
+        let mut ra = super::ReadAhead::new();
+        let mut reads = vec![];
+        let mut offset = 0;
+        for _ in 0..64 {
+            ra.read(offset, 4096, 16384, reader(1 << 20, &mut reads))
+                .unwrap();
+            offset += 4096;
+        }
+        assert!(reads.iter().all(|x| x.0 <= 16384), "{reads:?}");
+    }
+
+    #[test]
+    fn test_random() {
+        let mut ra = super::ReadAhead::new();
+        let mut reads = vec![];
+        for offset in [65536, 0, 32768, 8192] {
+            let v = ra
+                .read(offset, 4096, 65536, reader(1 << 20, &mut reads))
+                .unwrap();
+            assert_eq!(v, expected(4096, offset));
+        }
+        assert!(reads.iter().all(|x| x.0 == 4096), "{reads:?}");
+    }
+
+    #[test]
+    fn test_eof() {
+        let mut ra = super::ReadAhead::new();
+        let mut reads = vec![];
+        let fsize = 10000;
+        assert_eq!(
+            ra.read(0, 4096, 65536, reader(fsize, &mut reads)).unwrap(),
+            expected(4096, 0)
+        );
+        assert_eq!(
+            ra.read(4096, 4096, 65536, reader(fsize, &mut reads))
+                .unwrap(),
+            expected(4096, 4096)
+        );
+        assert_eq!(
+            ra.read(8192, 4096, 65536, reader(fsize, &mut reads))
+                .unwrap(),
+            expected(fsize - 8192, 8192)
+        );
+        assert!(
+            ra.read(fsize, 4096, 65536, reader(fsize, &mut reads))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut ra = super::ReadAhead::new();
+        let mut reads = vec![];
+        ra.read(u64::MAX - 10, 4096, u64::MAX, reader(0, &mut reads))
+            .unwrap();
+        ra.read(u64::MAX, 4096, u64::MAX, reader(0, &mut reads))
+            .unwrap();
+    }
+}
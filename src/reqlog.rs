@@ -0,0 +1,88 @@
+// per FUSE request record for JSON log format
+pub(crate) const TARGET: &str = "hammer2_fuse::request";
+
+static ENABLED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+thread_local! {
+    static ERRNO: std::cell::Cell<i32> = const { std::cell::Cell::new(0) };
+}
+
+pub(crate) fn enable() {
+    ENABLED.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+// called on error reply
+pub(crate) fn set_errno(errno: i32) -> i32 {
+    ERRNO.set(errno);
+    errno
+}
+
+pub(crate) fn get_errno() -> i32 {
+    ERRNO.get()
+}
+
+// logged on drop, hence declare before reply is consumed
+#[derive(Debug)]
+pub(crate) struct Record {
+    unique: u64,
+    opcode: &'static str,
+    pid: u32,
+    uid: u32,
+    gid: u32,
+    inum: u64,
+    offset: Option<i64>,
+    size: Option<u32>,
+    start: std::time::Instant,
+}
+
+impl Record {
+    pub(crate) fn new(req: &fuser::Request<'_>, opcode: &'static str, inum: u64) -> Self {
+        ERRNO.set(0);
+        Self {
+            unique: req.unique(),
+            opcode,
+            pid: req.pid(),
+            uid: req.uid(),
+            gid: req.gid(),
+            inum,
+            offset: None,
+            size: None,
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn offset(mut self, offset: i64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub(crate) fn size(mut self, size: u32) -> Self {
+        self.size = Some(size);
+        self
+    }
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        if !is_enabled() {
+            return;
+        }
+        let v = serde_json::json!({
+            "unique": self.unique,
+            "opcode": self.opcode,
+            "pid": self.pid,
+            "uid": self.uid,
+            "gid": self.gid,
+            "inum": self.inum,
+            "offset": self.offset,
+            "size": self.size,
+            "errno": get_errno(),
+            "duration_us": u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX),
+        });
+        log::info!(target: TARGET, "{v}");
+    }
+}
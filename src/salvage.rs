@@ -0,0 +1,317 @@
+use libhammer2::ErrorExt;
+
+// emergency read-only mode for damaged media, errors are recorded
+// in a damage report instead of failing the whole operation
+static DAMAGE: std::sync::Mutex<std::collections::BTreeMap<(u64, u64, &str), String>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum SalvageMode {
+    #[default]
+    Off,
+    Skip, // older volume header, partial directory listing
+    Zero, // also zero-fill unreadable data blocks
+}
+
+impl SalvageMode {
+    pub(crate) fn new(s: &str) -> nix::Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "zero" | "best_effort" => Ok(Self::Zero),
+            _ => {
+                eprintln!("invalid salvage mode {s}");
+                Err(nix::errno::Errno::EINVAL)
+            }
+        }
+    }
+}
+
+// inum 0 for volume level damage
+pub(crate) fn record<E: std::fmt::Display>(inum: u64, offset: u64, kind: &'static str, e: &E) {
+    let Ok(mut m) = DAMAGE.lock() else {
+        return;
+    };
+    if let std::collections::btree_map::Entry::Vacant(x) = m.entry((inum, offset, kind)) {
+        log::error!("inum {inum} offset {offset:#x} {kind}: {e}");
+        x.insert(e.to_string());
+    }
+}
+
+const REPORT_NAME: &str = ".hammer2-fuse.damage.json";
+
+// $HAMMER2_HOME, then home directory
+fn get_default_report_path() -> Option<String> {
+    let dir = match std::env::var(crate::HAMMER2_HOME) {
+        Ok(v) if libfs::fs::is_dir(&v) => v,
+        _ => crate::util::get_home_path().ok()?,
+    };
+    libfs::fs::join_path(&dir, REPORT_NAME)
+}
+
+// JSON lines, written on unmount
+pub(crate) fn write_report(path: Option<&str>) -> std::io::Result<()> {
+    let Ok(m) = DAMAGE.lock() else {
+        return Ok(());
+    };
+    if m.is_empty() {
+        return Ok(());
+    }
+    log::warn!("{} damaged objects", m.len());
+    let Some(path) = path.map(str::to_string).or_else(get_default_report_path) else {
+        log::error!("no path to write damage report");
+        return Ok(());
+    };
+    let mut s = String::new();
+    for ((inum, offset, kind), e) in m.iter() {
+        let v = serde_json::json!({
+            "inum": inum,
+            "offset": offset,
+            "kind": kind,
+            "error": e,
+        });
+        s.push_str(&format!("{v}\n"));
+    }
+    std::fs::write(&path, s)?;
+    log::info!("damage report written to {path}");
+    Ok(())
+}
+
+fn get_header_offset(i: usize) -> u64 {
+    u64::try_from(i).map_or(u64::MAX, |x| {
+        x.saturating_mul(libhammer2::fs::HAMMER2_ZONE_BYTES64)
+    })
+}
+
+// mount using older volume headers by masking newer ones,
+// libhammer2 always picks the one with highest mirror_tid
+pub(crate) fn mount(
+    special: &str,
+    selector: Option<&str>,
+    label: Option<&str>,
+    mopt: &[&str],
+) -> crate::Result<(libhammer2::hammer2::Hammer2, crate::blkfuse::Helper, String)> {
+    let mut src = crate::blksrc::open_raw(special, selector)?;
+    let (hdrs, _) = crate::probe::read_headers(&mut *src)?;
+    let mut l: Vec<_> = hdrs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| x.as_ref().map(|x| (i, x.mirror_tid)))
+        .collect();
+    l.sort_by_key(|x| std::cmp::Reverse(x.1));
+    for (i, tid) in l {
+        let ranges = (0..libhammer2::fs::HAMMER2_NUM_VOLHDRS)
+            .filter(|&x| x != i)
+            .map(|x| {
+                let offset = get_header_offset(x);
+                (
+                    offset,
+                    offset.saturating_add(libhammer2::fs::HAMMER2_PBUFSIZE),
+                )
+            })
+            .collect();
+        let src = crate::blksrc::mask(crate::blksrc::open_raw(special, selector)?, ranges);
+        let h = crate::blkfuse::mount(src)?;
+        let spec = h.get_spec(label);
+        match crate::mount_hammer2(&spec, mopt, false) {
+            Ok(v) => {
+                log::warn!("mounted with volume header {i} mirror_tid {tid:#x}");
+                return Ok((v, h, spec));
+            }
+            Err(e) => record(0, get_header_offset(i), "volume header", &e),
+        }
+    }
+    Err(Box::new(nix::errno::Errno::EIO))
+}
+
+// preadx with unreadable blocks zero-filled in Zero mode
+pub(crate) fn pread(
+    pmp: &mut libhammer2::hammer2::Hammer2,
+    dc: &mut Option<crate::datacache::DataCache>,
+    mode: SalvageMode,
+    inum: u64,
+    size: u64,
+    offset: u64,
+) -> libhammer2::Result<Vec<u8>> {
+    let e = match crate::datacache::pread(pmp, dc, inum, size, offset) {
+        Ok(v) => return Ok(v),
+        Err(e) => e,
+    };
+    match mode {
+        SalvageMode::Off => return Err(e),
+        SalvageMode::Skip => {
+            record(inum, offset, "data", &e);
+            return Err(e);
+        }
+        SalvageMode::Zero => (),
+    }
+    let Some(ip) = pmp.get_inode(inum) else {
+        return Err(e);
+    };
+    let end = std::cmp::min(offset.saturating_add(size), ip.get_meta().size);
+    let bsize = libhammer2::fs::HAMMER2_PBUFSIZE;
+    let mut v = vec![];
+    let mut x = offset;
+    while x < end {
+        let n = std::cmp::min((x / bsize + 1) * bsize, end) - x;
+        match crate::datacache::pread(pmp, dc, inum, n, x) {
+            Ok(b) => v.extend_from_slice(&b),
+            Err(e) => {
+                record(inum, x - x % bsize, "data", &e);
+                v.resize(v.len() + usize::try_from(n).or_range()?, 0);
+            }
+        }
+        x += n;
+    }
+    Ok(v)
+}
+
+// key range covered by bref
+fn get_key_range(bref: &libhammer2::fs::Hammer2Blockref) -> Option<(u64, u64)> {
+    let n = 1_u64.checked_shl(bref.keybits.into())?;
+    Some((bref.key, bref.key.checked_add(n - 1)?))
+}
+
+// key range of chain if indirect block
+fn get_indirect_range(
+    pmp: &libhammer2::hammer2::Hammer2,
+    cid: libhammer2::chain::Cid,
+) -> Option<(u64, u64)> {
+    let bref = pmp.get_chain(cid)?.get_blockref();
+    if bref.typ != libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT {
+        return None;
+    }
+    get_key_range(bref)
+}
+
+// key range of first child of pcid not below key, i.e. the one
+// lookup failed to load
+fn get_child_range(
+    pmp: &libhammer2::hammer2::Hammer2,
+    pcid: libhammer2::chain::Cid,
+    key: u64,
+) -> Option<(u64, u64)> {
+    let chain = pmp.get_chain(pcid)?;
+    let b = match chain.get_blockref().typ {
+        libhammer2::fs::HAMMER2_BREF_TYPE_INODE => {
+            let ipdata = chain.as_inode_data();
+            if ipdata.meta.op_flags & libhammer2::fs::HAMMER2_OPFLAG_DIRECTDATA != 0 {
+                return None;
+            }
+            &ipdata.u[..]
+        }
+        libhammer2::fs::HAMMER2_BREF_TYPE_INDIRECT => chain.get_data(),
+        _ => return None,
+    };
+    b.chunks_exact(libhammer2::fs::HAMMER2_BLOCKREF_BYTES)
+        .map(libfs::cast::align_to::<libhammer2::fs::Hammer2Blockref>)
+        .filter(|x| x.typ != libhammer2::fs::HAMMER2_BREF_TYPE_EMPTY)
+        .filter_map(get_key_range)
+        .filter(|&(_, end)| end >= key)
+        .min()
+}
+
+impl crate::Hammer2Fuse {
+    // dirents and raw names
+    pub(crate) fn readdir_salvage(
+        &mut self,
+        dinum: u64,
+    ) -> libhammer2::Result<(Vec<libhammer2::hammer2::Dirent>, Vec<std::ffi::OsString>)> {
+        match self.pmp.readdir(dinum) {
+            Ok(v) => {
+                let names = self.get_dirent_names(dinum, &v);
+                Ok((v, names))
+            }
+            Err(e) if self.salvage != SalvageMode::Off => {
+                record(dinum, 0, "directory", &e);
+                self.scan_dirents(dinum)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // walk directory chains, skipping key range of the child that failed
+    // to load, or of the indirect block the walk was in if unknown
+    fn scan_dirents(
+        &mut self,
+        dinum: u64,
+    ) -> libhammer2::Result<(Vec<libhammer2::hammer2::Dirent>, Vec<std::ffi::OsString>)> {
+        let Some(dip) = self.pmp.get_inode(dinum) else {
+            return Err(nix::errno::Errno::ENOENT.into());
+        };
+        let iparent = match dip.get_meta().iparent {
+            0 => dinum,
+            v => v,
+        };
+        let mut v = vec![
+            libhammer2::hammer2::Dirent {
+                inum: dinum,
+                typ: libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY,
+                name: ".".to_string(),
+            },
+            libhammer2::hammer2::Dirent {
+                inum: iparent,
+                typ: libhammer2::fs::HAMMER2_OBJTYPE_DIRECTORY,
+                name: "..".to_string(),
+            },
+        ];
+        let mut names: Vec<std::ffi::OsString> = v.iter().map(|e| e.name.clone().into()).collect();
+        let dcid = self
+            .pmp
+            .get_inode_chain(dinum, libhammer2::hammer2::RESOLVE_ALWAYS)?;
+        if dcid == libhammer2::chain::CID_NONE {
+            return Err(nix::errno::Errno::EIO.into());
+        }
+        let key_end = libhammer2::fs::HAMMER2_KEY_MAX;
+        let mut key = libhammer2::fs::HAMMER2_DIRHASH_VISIBLE;
+        let mut range = None;
+        loop {
+            let (e, child) = match self.pmp.lookup_chain(dcid, key, key_end, 0) {
+                Ok((mut pcid, mut cid, _)) => loop {
+                    if cid == libhammer2::chain::CID_NONE {
+                        return Ok((v, names));
+                    }
+                    range = get_indirect_range(&self.pmp, pcid);
+                    let chain = self.get_chain(cid)?;
+                    let bref_key = chain.get_blockref().key;
+                    key = bref_key.saturating_add(1);
+                    match crate::dirent::get_chain_dirent(chain) {
+                        Ok(Some((inum, typ, Some(name)))) => {
+                            v.push(libhammer2::hammer2::Dirent {
+                                inum,
+                                typ,
+                                name: String::from_utf8_lossy(&name).into_owned(),
+                            });
+                            names.push(std::os::unix::ffi::OsStringExt::from_vec(name));
+                        }
+                        Ok(Some((inum, ..))) => {
+                            log::warn!("dinum {dinum}: inum {inum} name not readable")
+                        }
+                        Ok(None) => (),
+                        Err(e) => record(dinum, bref_key, "directory entry", &e),
+                    }
+                    match self.pmp.get_next_chain(pcid, cid, key_end, 0) {
+                        Ok(x) => (pcid, cid, _) = x,
+                        Err(e) => break (e, get_child_range(&self.pmp, pcid, key)),
+                    }
+                },
+                Err(e) => (e, get_child_range(&self.pmp, dcid, key)),
+            };
+            record(dinum, key, "directory block", &e);
+            let skip = child.or_else(|| {
+                range
+                    .take()
+                    .filter(|(beg, end)| (*beg..=*end).contains(&key))
+            });
+            match skip {
+                Some((_, end)) => match end.checked_add(1) {
+                    Some(x) => key = x,
+                    None => return Ok((v, names)),
+                },
+                None => {
+                    log::warn!("dinum {dinum}: entries from key {key:#x} skipped");
+                    return Ok((v, names));
+                }
+            }
+        }
+    }
+}
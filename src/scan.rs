@@ -0,0 +1,179 @@
+// assemble multi-volume special from a directory or glob,
+// e.g. "/dev/da*" or "/images", glob in last path component only
+#[derive(Debug)]
+struct Volume {
+    path: String,
+    hdr: crate::probe::Header,
+}
+
+// '*' and '?' only, backtracks to last '*'
+fn match_glob(pat: &[u8], s: &[u8]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    let mut star = None;
+    while j < s.len() {
+        match pat.get(i) {
+            Some(b'*') => {
+                star = Some((i, j));
+                i += 1;
+            }
+            Some(&c) if c == b'?' || c == s[j] => {
+                i += 1;
+                j += 1;
+            }
+            _ => match star {
+                Some((x, y)) => {
+                    star = Some((x, y + 1));
+                    i = x + 1;
+                    j = y + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pat[i..].iter().all(|&c| c == b'*')
+}
+
+fn get_candidates(pattern: &str) -> std::io::Result<Vec<String>> {
+    let (dir, pat) = if libfs::fs::is_dir(pattern) {
+        (pattern, "*")
+    } else {
+        match pattern.rsplit_once('/') {
+            Some(("", b)) => ("/", b),
+            Some((a, b)) => (a, b),
+            None => (".", pattern),
+        }
+    };
+    let mut v = vec![];
+    for x in std::fs::read_dir(dir)? {
+        let x = x?;
+        let name = x.file_name();
+        if match_glob(
+            pat.as_bytes(),
+            std::os::unix::ffi::OsStrExt::as_bytes(&*name),
+        ) {
+            v.push(x.path().to_string_lossy().into_owned());
+        }
+    }
+    v.sort();
+    Ok(v)
+}
+
+fn read_volume(path: &str) -> Option<Volume> {
+    let mut src = match crate::blksrc::open_raw(path, None) {
+        Ok(v) => v,
+        Err(e) => {
+            log::debug!("{path}: {e}");
+            return None;
+        }
+    };
+    let (mut hdrs, best) = match crate::probe::read_headers(&mut *src) {
+        Ok(v) => v,
+        Err(e) => {
+            log::debug!("{path}: {e}");
+            return None;
+        }
+    };
+    Some(Volume {
+        path: path.to_string(),
+        hdr: hdrs[best?].take()?,
+    })
+}
+
+// validate volumes of the same fsid
+fn check_volumes(v: &[Volume]) -> bool {
+    let n = usize::from(v[0].hdr.nvolumes);
+    if v.len() != n {
+        log::error!("fsid {}: {} of {n} volumes found", v[0].hdr.fsid, v.len());
+        return false;
+    }
+    for (i, x) in v.iter().enumerate() {
+        if usize::from(x.hdr.volu_id) != i {
+            log::error!("fsid {}: volume {i} missing or duplicated", x.hdr.fsid);
+            return false;
+        }
+        if x.hdr.nvolumes != v[0].hdr.nvolumes || x.hdr.version != v[0].hdr.version {
+            log::error!("{}: inconsistent with {}", x.path, v[0].path);
+            return false;
+        }
+    }
+    true
+}
+
+// returns "vol0:vol1:...[@label]"
+pub(crate) fn assemble(spec: &str) -> crate::Result<String> {
+    let (pattern, label) = match spec.split_once('@') {
+        Some((a, b)) => (a, Some(b)),
+        None => (spec, None),
+    };
+    let mut m = std::collections::BTreeMap::<_, Vec<_>>::new();
+    for x in get_candidates(pattern)? {
+        if let Some(v) = read_volume(&x) {
+            log::info!("{x}: fsid {} volu_id {}", v.hdr.fsid, v.hdr.volu_id);
+            m.entry(v.hdr.fsid.clone()).or_default().push(v);
+        }
+    }
+    let mut l = vec![];
+    for (fsid, mut v) in m {
+        v.sort_by_key(|x| x.hdr.volu_id);
+        if v.len() > libhammer2::fs::HAMMER2_MAX_VOLUMES.into() {
+            log::error!("fsid {fsid}: too many volumes");
+        } else if check_volumes(&v) {
+            l.push(v);
+        }
+    }
+    let v = match l.len() {
+        1 => l.pop().unwrap(),
+        0 => {
+            eprintln!("{pattern}: no complete HAMMER2 file system found");
+            return Err(Box::new(nix::errno::Errno::ENOENT));
+        }
+        _ => {
+            for v in &l {
+                eprintln!("fsid {} found", v[0].hdr.fsid);
+            }
+            eprintln!("{pattern}: multiple HAMMER2 file systems found");
+            return Err(Box::new(nix::errno::Errno::EEXIST));
+        }
+    };
+    let special = v
+        .iter()
+        .map(|x| x.path.as_str())
+        .collect::<Vec<_>>()
+        .join(":");
+    Ok(match label {
+        Some(x) => format!("{special}@{x}"),
+        None => special,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    fn is_match(pat: &str, s: &str) -> bool {
+        super::match_glob(pat.as_bytes(), s.as_bytes())
+    }
+
+    #[test]
+    fn test_match_glob() {
+        assert!(is_match("*", ""));
+        assert!(is_match("*", "disk.img"));
+        assert!(is_match("*.img", "disk.img"));
+        assert!(is_match("disk?.img", "disk0.img"));
+        assert!(is_match("d*k*.img", "disk0.img"));
+        assert!(is_match("a*b*c", "abxbc"));
+        assert!(is_match("**", "x"));
+        assert!(is_match("", ""));
+        assert!(!is_match("", "x"));
+        assert!(!is_match("*.img", "disk.img0"));
+        assert!(!is_match("disk?.img", "disk.img"));
+        assert!(!is_match("?", ""));
+        assert!(!is_match("a*b", "acbx"));
+    }
+
+    #[test]
+    fn test_match_glob_pathological() {
+        let pat = format!("{}b", "a*".repeat(32));
+        let s = "a".repeat(1000);
+        assert!(!is_match(&pat, &s));
+        assert!(is_match(&pat, &format!("{s}b")));
+    }
+}
@@ -0,0 +1,77 @@
+// "special@label:/path" mounts a directory of PFS as root,
+// by swapping its inode number with FUSE root inode number
+impl crate::Hammer2Fuse {
+    fn swap_inum(&self, inum: u64) -> u64 {
+        if inum == fuser::FUSE_ROOT_ID {
+            self.root
+        } else if inum == self.root {
+            fuser::FUSE_ROOT_ID
+        } else {
+            inum
+        }
+    }
+
+    pub(crate) fn get_h2_inum(&self, ino: u64) -> u64 {
+        self.swap_inum(ino)
+    }
+
+    pub(crate) fn get_fuse_inum(&self, inum: u64) -> u64 {
+        self.swap_inum(inum)
+    }
+}
+
+pub(crate) fn split_spec(spec: &str) -> (String, Option<String>) {
+    if let Some((special, label)) = spec.split_once('@')
+        && let Some((label, path)) = label.split_once(":/")
+    {
+        (format!("{special}@{label}"), Some(format!("/{path}")))
+    } else {
+        (spec.to_string(), None)
+    }
+}
+
+pub(crate) fn resolve(pmp: &mut libhammer2::hammer2::Hammer2, path: &str) -> crate::Result<u64> {
+    let mut inum = libhammer2::inode::INUM_PFS_ROOT;
+    for x in path.split('/').filter(|x| !x.is_empty() && *x != ".") {
+        if x == ".." {
+            return Err(Box::new(nix::errno::Errno::EINVAL));
+        }
+        inum = pmp.nresolve(inum, x)?;
+    }
+    match pmp.get_inode(inum) {
+        Some(ip) if ip.is_directory() => Ok(inum),
+        Some(_) => Err(Box::new(nix::errno::Errno::ENOTDIR)),
+        None => Err(Box::new(nix::errno::Errno::ENOENT)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_split_spec() {
+        assert_eq!(
+            super::split_spec("/dev/da0@ROOT:/home/user"),
+            ("/dev/da0@ROOT".to_string(), Some("/home/user".to_string()))
+        );
+        assert_eq!(
+            super::split_spec("/dev/da0@ROOT:/"),
+            ("/dev/da0@ROOT".to_string(), Some("/".to_string()))
+        );
+        assert_eq!(
+            super::split_spec("disk.img#s1a@DATA:/a:/b"),
+            ("disk.img#s1a@DATA".to_string(), Some("/a:/b".to_string()))
+        );
+        assert_eq!(
+            super::split_spec("/dev/da0@ROOT"),
+            ("/dev/da0@ROOT".to_string(), None)
+        );
+        assert_eq!(
+            super::split_spec("/mnt/a:/b"),
+            ("/mnt/a:/b".to_string(), None)
+        );
+        assert_eq!(
+            super::split_spec("/dev/da0"),
+            ("/dev/da0".to_string(), None)
+        );
+    }
+}
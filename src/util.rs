@@ -1,5 +1,21 @@
 use libhammer2::ErrorExt;
 
+// DragonFly chflags(2) flags stored in inode uflags
+const UF_NODUMP: u32 = 0x0000_0001;
+const UF_IMMUTABLE: u32 = 0x0000_0002;
+const UF_APPEND: u32 = 0x0000_0004;
+const UF_OPAQUE: u32 = 0x0000_0008;
+const UF_NOUNLINK: u32 = 0x0000_0010;
+const SF_ARCHIVED: u32 = 0x0001_0000;
+const SF_IMMUTABLE: u32 = 0x0002_0000;
+const SF_APPEND: u32 = 0x0004_0000;
+const SF_NOUNLINK: u32 = 0x0010_0000;
+
+// Linux FS_IOC_GETFLAGS flags
+const FS_IMMUTABLE_FL: u32 = 0x0000_0010;
+const FS_APPEND_FL: u32 = 0x0000_0020;
+const FS_NODUMP_FL: u32 = 0x0000_0040;
+
 pub(crate) fn get_home_path() -> nix::Result<String> {
     home::home_dir()
         .ok_or(nix::errno::Errno::ENOENT)?
@@ -8,16 +24,52 @@ pub(crate) fn get_home_path() -> nix::Result<String> {
         .map_err(|_| nix::errno::Errno::EINVAL)
 }
 
-pub(crate) fn stat2attr(st: &libhammer2::hammer2::Stat) -> nix::Result<fuser::FileAttr> {
+// UTC in "YYYY-MM-DD HH:MM:SS.uuuuuu" format
+pub(crate) fn get_time_string() -> String {
+    let t = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = t.as_secs();
+    let (h, m, s) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = secs / 86400 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {h:02}:{m:02}:{s:02}.{:06}",
+        t.subsec_micros()
+    )
+}
+
+// HAMMER2 inode timestamps are in microseconds
+pub(crate) fn time2system(t: u64) -> std::time::SystemTime {
+    std::time::UNIX_EPOCH + std::time::Duration::from_micros(t)
+}
+
+pub(crate) fn stat2attr(
+    st: &libhammer2::hammer2::Stat,
+    meta: Option<&libhammer2::fs::Hammer2InodeMeta>,
+    idmap: &crate::idmap::IdMaps,
+) -> nix::Result<fuser::FileAttr> {
     let mtime = libfs::time::unix2system(st.st_mtime);
-    Ok(fuser::FileAttr {
+    let (ctime, crtime) = match meta {
+        Some(v) => (time2system(v.ctime), time2system(v.btime)),
+        None => (mtime, mtime),
+    };
+    let mut attr = fuser::FileAttr {
         ino: st.st_ino,
         size: st.st_size,
         blocks: st.st_blocks,
         atime: libfs::time::unix2system(st.st_atime),
         mtime,
-        ctime: mtime,
-        crtime: mtime,
+        ctime,
+        crtime,
         kind: mode2kind(st.st_mode),
         perm: (st.st_mode & 0o777).try_into().or_nix_range()?,
         nlink: st.st_nlink,
@@ -25,8 +77,46 @@ pub(crate) fn stat2attr(st: &libhammer2::hammer2::Stat) -> nix::Result<fuser::Fi
         gid: st.st_gid,
         rdev: st.st_rdev,
         blksize: st.st_blksize,
-        flags: 0,
-    })
+        flags: meta.map_or(0, |v| uflags2bsd(v.uflags)),
+    };
+    idmap.map_attr(&mut attr);
+    Ok(attr)
+}
+
+pub(crate) fn is_immutable(uflags: u32) -> bool {
+    uflags & (UF_IMMUTABLE | SF_IMMUTABLE) != 0
+}
+
+pub(crate) fn is_append(uflags: u32) -> bool {
+    uflags & (UF_APPEND | SF_APPEND) != 0
+}
+
+// UF_NOHISTORY, UF_CACHE, SF_NOHISTORY, SF_NOCACHE, etc are DragonFly specific
+pub(crate) fn uflags2bsd(uflags: u32) -> u32 {
+    uflags
+        & (UF_NODUMP
+            | UF_IMMUTABLE
+            | UF_APPEND
+            | UF_OPAQUE
+            | UF_NOUNLINK
+            | SF_ARCHIVED
+            | SF_IMMUTABLE
+            | SF_APPEND
+            | SF_NOUNLINK)
+}
+
+pub(crate) fn uflags2linux(uflags: u32) -> u32 {
+    let mut flags = 0;
+    if uflags & UF_NODUMP != 0 {
+        flags |= FS_NODUMP_FL;
+    }
+    if is_immutable(uflags) {
+        flags |= FS_IMMUTABLE_FL;
+    }
+    if is_append(uflags) {
+        flags |= FS_APPEND_FL;
+    }
+    flags
 }
 
 pub(crate) fn mode2kind(mode: libhammer2::hammer2::StatMode) -> fuser::FileType {
@@ -54,3 +144,63 @@ pub(crate) fn obj2kind(typ: u8) -> fuser::FileType {
         _ => panic!("{typ}"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_uflags2bsd() {
+        for (uflags, flags) in [
+            (0, 0),
+            (super::UF_NODUMP, super::UF_NODUMP),
+            (super::UF_IMMUTABLE, super::UF_IMMUTABLE),
+            (super::UF_APPEND, super::UF_APPEND),
+            (super::UF_OPAQUE, super::UF_OPAQUE),
+            (super::UF_NOUNLINK, super::UF_NOUNLINK),
+            (super::SF_ARCHIVED, super::SF_ARCHIVED),
+            (super::SF_IMMUTABLE, super::SF_IMMUTABLE),
+            (super::SF_APPEND, super::SF_APPEND),
+            (super::SF_NOUNLINK, super::SF_NOUNLINK),
+            (0x0000_0040, 0), // UF_NOHISTORY
+            (0x0000_0080, 0), // UF_CACHE
+            (0x0040_0000, 0), // SF_NOHISTORY
+            (
+                super::UF_NODUMP | super::SF_APPEND | 0x0000_0040,
+                super::UF_NODUMP | super::SF_APPEND,
+            ),
+        ] {
+            assert_eq!(super::uflags2bsd(uflags), flags, "{uflags:#x}");
+        }
+    }
+
+    #[test]
+    fn test_uflags2linux() {
+        for (uflags, flags) in [
+            (0, 0),
+            (super::UF_NODUMP, super::FS_NODUMP_FL),
+            (super::UF_IMMUTABLE, super::FS_IMMUTABLE_FL),
+            (super::SF_IMMUTABLE, super::FS_IMMUTABLE_FL),
+            (super::UF_APPEND, super::FS_APPEND_FL),
+            (super::SF_APPEND, super::FS_APPEND_FL),
+            (super::UF_OPAQUE, 0),
+            (super::UF_NOUNLINK, 0),
+            (super::SF_ARCHIVED, 0),
+            (super::SF_NOUNLINK, 0),
+            (
+                super::UF_NODUMP | super::UF_IMMUTABLE | super::SF_APPEND,
+                super::FS_NODUMP_FL | super::FS_IMMUTABLE_FL | super::FS_APPEND_FL,
+            ),
+        ] {
+            assert_eq!(super::uflags2linux(uflags), flags, "{uflags:#x}");
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        assert!(super::is_immutable(super::UF_IMMUTABLE));
+        assert!(super::is_immutable(super::SF_IMMUTABLE));
+        assert!(!super::is_immutable(super::UF_APPEND));
+        assert!(super::is_append(super::UF_APPEND));
+        assert!(super::is_append(super::SF_APPEND));
+        assert!(!super::is_append(super::SF_IMMUTABLE));
+    }
+}